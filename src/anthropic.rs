@@ -1,15 +1,371 @@
+const MAX_ATTEMPTS: u32 = 4;
+const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com";
+
+/// An API key, and where to record the usage of the calls made with it.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    api_token: std::sync::Arc<str>,
+    base_url: std::sync::Arc<str>,
+    ledger: Option<std::sync::Arc<crate::usage::Ledger>>,
+    call_site: crate::usage::CallSite,
+}
+
+impl Client {
+    pub fn new(http: reqwest::Client, api_token: impl Into<std::sync::Arc<str>>) -> Self {
+        Client {
+            http,
+            api_token: api_token.into(),
+            base_url: DEFAULT_BASE_URL.into(),
+            ledger: None,
+            call_site: crate::usage::CallSite::Other,
+        }
+    }
+
+    /// Send requests to another server that speaks the Messages API, e.g. a
+    /// proxy or [crate::mock_anthropic::MockServer].
+    pub fn base_url(mut self, base_url: impl Into<std::sync::Arc<str>>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Record usage in `ledger`, and refuse to make calls once its budget is
+    /// used up.
+    pub fn ledger(mut self, ledger: std::sync::Arc<crate::usage::Ledger>) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// A client that records its usage against `call_site`.
+    pub fn call_site(&self, call_site: crate::usage::CallSite) -> Self {
+        Client {
+            call_site,
+            ..self.clone()
+        }
+    }
+
+    async fn check_budget(&self) -> Result<(), AnthropicError> {
+        let Some(ledger) = &self.ledger else {
+            return Ok(());
+        };
+        match ledger.check_budget().await {
+            Ok(None) => Ok(()),
+            Ok(Some(exceeded)) => Err(AnthropicError::BudgetExceeded(exceeded)),
+            // Don't turn off LLM features because the ledger is unreadable.
+            Err(err) => {
+                tracing::warn!(error = ?err, "Error checking LLM budget");
+                Ok(())
+            }
+        }
+    }
+
+    async fn record_usage(&self, model: &str, usage: crate::usage::Usage) {
+        let Some(ledger) = &self.ledger else {
+            return;
+        };
+        if let Err(err) = ledger.record(self.call_site, model, usage).await {
+            tracing::warn!(error = ?err, "Error recording LLM usage");
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AnthropicError {
+    /// HTTP 429.
+    RateLimited {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
+    /// HTTP 529: the API is temporarily overloaded.
+    Overloaded {
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    },
+    /// HTTP 401 or 403: the API key is missing, invalid or not allowed.
+    Authentication { message: String },
+    /// Any other error response from the API.
+    Api {
+        status: reqwest::StatusCode,
+        error_type: String,
+        message: String,
+    },
+    /// The model declined to answer.
+    Refusal,
+    /// The response had no text in it, or didn't call the tool it was told
+    /// to.
+    EmptyContent { stop_reason: Option<String> },
+    /// The model called a tool with input that doesn't match its schema.
+    InvalidToolInput {
+        tool: String,
+        source: serde_json::Error,
+    },
+    /// A streamed response ended before the message did.
+    IncompleteStream,
+    /// Content in a format the API doesn't accept.
+    UnsupportedMediaType { media_type: String },
+    /// The ledger's daily or monthly budget has been spent, so no request
+    /// was made.
+    BudgetExceeded(crate::usage::BudgetExceeded),
+    /// The request couldn't be sent or the response couldn't be read.
+    Http(reqwest::Error),
+}
+
+impl AnthropicError {
+    /// Whether the same request might succeed if it's tried again later.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AnthropicError::RateLimited { .. } | AnthropicError::Overloaded { .. } => true,
+            AnthropicError::Api { status, .. } => status.is_server_error(),
+            AnthropicError::Http(err) => err.is_connect() || err.is_timeout(),
+            _ => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            AnthropicError::RateLimited { retry_after, .. }
+            | AnthropicError::Overloaded { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    async fn from_response(response: reqwest::Response) -> Self {
+        #[derive(serde::Deserialize)]
+        struct ErrorBody {
+            error: ErrorDetail,
+        }
+
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok());
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(err) => return AnthropicError::Http(err),
+        };
+        let (error_type, message) = match serde_json::from_str::<ErrorBody>(&body) {
+            Ok(ErrorBody { error }) => (error.type_, error.message),
+            Err(_) => (String::new(), body),
+        };
+        Self::from_parts(status, error_type, message, retry_after)
+    }
+
+    /// An error reported in the middle of a stream, which has no HTTP status
+    /// of its own.
+    fn from_stream(error: ErrorDetail) -> Self {
+        let status = match error.type_.as_str() {
+            "invalid_request_error" => reqwest::StatusCode::BAD_REQUEST,
+            "authentication_error" => reqwest::StatusCode::UNAUTHORIZED,
+            "permission_error" => reqwest::StatusCode::FORBIDDEN,
+            "not_found_error" => reqwest::StatusCode::NOT_FOUND,
+            "request_too_large" => reqwest::StatusCode::PAYLOAD_TOO_LARGE,
+            "rate_limit_error" => reqwest::StatusCode::TOO_MANY_REQUESTS,
+            _ => reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::from_parts(status, error.type_, error.message, None)
+    }
+
+    fn from_parts(
+        status: reqwest::StatusCode,
+        error_type: String,
+        message: String,
+        retry_after: Option<std::time::Duration>,
+    ) -> Self {
+        match status.as_u16() {
+            429 => AnthropicError::RateLimited {
+                message,
+                retry_after,
+            },
+            529 => AnthropicError::Overloaded {
+                message,
+                retry_after,
+            },
+            401 | 403 => AnthropicError::Authentication { message },
+            _ if error_type == "overloaded_error" => AnthropicError::Overloaded {
+                message,
+                retry_after,
+            },
+            _ => AnthropicError::Api {
+                status,
+                error_type,
+                message,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for AnthropicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnthropicError::RateLimited { message, .. } => {
+                write!(f, "Anthropic API rate limit exceeded: {message}")
+            }
+            AnthropicError::Overloaded { message, .. } => {
+                write!(f, "Anthropic API is overloaded: {message}")
+            }
+            AnthropicError::Authentication { message } => {
+                write!(f, "Anthropic API authentication failed: {message}")
+            }
+            AnthropicError::Api {
+                status,
+                error_type,
+                message,
+            } => write!(
+                f,
+                "Anthropic API returned {status} ({error_type}): {message}"
+            ),
+            AnthropicError::Refusal => write!(f, "The model refused to respond"),
+            AnthropicError::EmptyContent { stop_reason } => write!(
+                f,
+                "No text content in response (stop reason: {})",
+                stop_reason.as_deref().unwrap_or("none")
+            ),
+            AnthropicError::IncompleteStream => {
+                write!(
+                    f,
+                    "The response stream ended before the message was complete"
+                )
+            }
+            AnthropicError::UnsupportedMediaType { media_type } => {
+                write!(f, "The Anthropic API doesn't accept {media_type} content")
+            }
+            AnthropicError::BudgetExceeded(exceeded) => write!(
+                f,
+                "The {} LLM budget of ${:.2} has been used up (${:.4} spent)",
+                exceeded.period, exceeded.budget, exceeded.spent
+            ),
+            AnthropicError::InvalidToolInput { tool, source } => {
+                write!(f, "Invalid input for tool {tool}: {source}")
+            }
+            AnthropicError::Http(err) => write!(f, "Error talking to the Anthropic API: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for AnthropicError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AnthropicError::Http(err) => Some(err),
+            AnthropicError::InvalidToolInput { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct ErrorDetail {
+    #[serde(rename = "type")]
+    type_: String,
+    message: String,
+}
+
 #[derive(serde::Serialize)]
 struct CompletionRequest<'a> {
-    model: &'static str,
-    messages: Vec<CompletionRequestMessage<'a>>,
-    max_tokens: usize,
+    model: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    system: &'a [MessageContent<'a>],
+    messages: &'a [CompletionRequestMessage<'a>],
+    max_tokens: u32,
     thinking: Thinking,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop_sequences: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [Tool<'a>],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice<'a>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
 }
 
+/// A tool the model can call. `input_schema` is the JSON Schema for its
+/// input.
 #[derive(serde::Serialize)]
-struct Thinking {
-    #[serde(rename = "type")]
-    type_: &'static str,
+pub struct Tool<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub input_schema: serde_json::Value,
+}
+
+#[derive(serde::Serialize, Clone, Copy)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice<'a> {
+    Auto,
+    Any,
+    Tool { name: &'a str },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Thinking {
+    /// Let the model decide how much to think.
+    Adaptive,
+    Enabled {
+        budget_tokens: u32,
+    },
+    Disabled,
+}
+
+/// Per-call settings for [get_completion]. Fields missing from a config
+/// file take the values from [CompletionOptions::default].
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CompletionOptions {
+    pub model: String,
+    pub max_tokens: u32,
+    pub thinking: Thinking,
+    /// The API only accepts this with thinking disabled.
+    pub temperature: Option<f32>,
+    pub stop_sequences: Vec<String>,
+}
+
+impl Default for CompletionOptions {
+    fn default() -> Self {
+        CompletionOptions {
+            model: "claude-fable-5".to_string(),
+            max_tokens: 16000,
+            thinking: Thinking::Adaptive,
+            temperature: None,
+            stop_sequences: Vec::new(),
+        }
+    }
+}
+
+impl CompletionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn thinking(mut self, thinking: Thinking) -> Self {
+        self.thinking = thinking;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn stop_sequences(mut self, stop_sequences: Vec<String>) -> Self {
+        self.stop_sequences = stop_sequences;
+        self
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -18,76 +374,609 @@ struct CompletionRequestMessage<'a> {
     content: Vec<MessageContent<'a>>,
 }
 
+/// A system prompt and the turns of a conversation so far.
+///
+/// Consecutive turns from the same side are merged into one message. If the
+/// last turn is the assistant's, the model continues it (a "prefill"), and
+/// the completion is only the continuation. The API doesn't allow a prefill
+/// while thinking is enabled.
+#[derive(Default)]
+pub struct Conversation<'a> {
+    system: Vec<MessageContent<'a>>,
+    messages: Vec<CompletionRequestMessage<'a>>,
+}
+
+impl<'a> Conversation<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn system(mut self, prompt: &'a str) -> Self {
+        self.system.push(MessageContent::Text {
+            text: prompt,
+            cache_control: None,
+        });
+        self
+    }
+
+    /// Like [Conversation::system], but cache the prompt, and any tools, so
+    /// requests that share them only pay full price for the rest. The API
+    /// ignores prefixes shorter than the model's minimum cacheable length.
+    pub fn cached_system(mut self, prompt: &'a str) -> Self {
+        self.system.push(MessageContent::Text {
+            text: prompt,
+            cache_control: Some(CacheControl::Ephemeral),
+        });
+        self
+    }
+
+    pub fn user(self, content: impl IntoIterator<Item = MessageContent<'a>>) -> Self {
+        self.push("user", content)
+    }
+
+    pub fn assistant(self, text: &'a str) -> Self {
+        self.push(
+            "assistant",
+            [MessageContent::Text {
+                text,
+                cache_control: None,
+            }],
+        )
+    }
+
+    /// The system prompt, as blocks of text.
+    pub fn system_blocks(&self) -> &[MessageContent<'a>] {
+        &self.system
+    }
+
+    /// Each turn's role ("user" or "assistant") and content, in order.
+    pub fn turns(&self) -> impl Iterator<Item = (&'static str, &[MessageContent<'a>])> {
+        self.messages
+            .iter()
+            .map(|message| (message.role, message.content.as_slice()))
+    }
+
+    fn push(
+        mut self,
+        role: &'static str,
+        content: impl IntoIterator<Item = MessageContent<'a>>,
+    ) -> Self {
+        match self.messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(content),
+            _ => self.messages.push(CompletionRequestMessage {
+                role,
+                content: content.into_iter().collect(),
+            }),
+        }
+        self
+    }
+}
+
+/// A block of message content. Setting `cache_control` on a block caches
+/// the request up to and including it.
 #[derive(serde::Serialize)]
 #[serde(tag = "type")]
 pub enum MessageContent<'a> {
     #[serde(rename = "text")]
-    Text { text: &'a str },
+    Text {
+        text: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
     #[serde(rename = "image")]
-    Image { source: ImageSource<'a> },
+    Image {
+        source: ImageSource<'a>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// A PDF, which the model reads as both text and page images.
+    #[serde(rename = "document")]
+    Document {
+        source: DocumentSource<'a>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CacheControl {
+    /// Kept for five minutes after it was last used.
+    Ephemeral,
+}
+
+/// The image formats the API accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageMediaType {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+}
+
+impl ImageMediaType {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageMediaType::Jpeg => "image/jpeg",
+            ImageMediaType::Png => "image/png",
+            ImageMediaType::Gif => "image/gif",
+            ImageMediaType::Webp => "image/webp",
+        }
+    }
+
+    /// Parse a MIME type, e.g. from a `Content-Type` header.
+    pub fn from_mime_type(mime_type: &str) -> Result<Self, AnthropicError> {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        [
+            ImageMediaType::Jpeg,
+            ImageMediaType::Png,
+            ImageMediaType::Gif,
+            ImageMediaType::Webp,
+        ]
+        .into_iter()
+        .find(|t| t.mime_type().eq_ignore_ascii_case(essence))
+        .ok_or_else(|| AnthropicError::UnsupportedMediaType {
+            media_type: mime_type.to_string(),
+        })
+    }
+
+    pub fn from_format(format: image::ImageFormat) -> Result<Self, AnthropicError> {
+        match format {
+            image::ImageFormat::Jpeg => Ok(ImageMediaType::Jpeg),
+            image::ImageFormat::Png => Ok(ImageMediaType::Png),
+            image::ImageFormat::Gif => Ok(ImageMediaType::Gif),
+            image::ImageFormat::WebP => Ok(ImageMediaType::Webp),
+            _ => Err(AnthropicError::UnsupportedMediaType {
+                media_type: format.to_mime_type().to_string(),
+            }),
+        }
+    }
+}
+
+impl serde::Serialize for ImageMediaType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.mime_type())
+    }
 }
 
 #[derive(serde::Serialize)]
-pub struct ImageSource<'a> {
-    #[serde(rename = "type")]
-    type_: &'static str,
-    data: &'a str,
-    media_type: &'static str,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Source<'a, M> {
+    Base64 { media_type: M, data: &'a str },
+    Url { url: &'a str },
 }
 
+#[derive(serde::Serialize)]
+#[serde(transparent)]
+pub struct ImageSource<'a>(Source<'a, ImageMediaType>);
+
 impl<'a> ImageSource<'a> {
-    pub fn new_base64(media_type: &'static str, data: &'a str) -> Self {
-        ImageSource {
-            type_: "base64",
-            data,
-            media_type,
+    pub fn new_base64(media_type: ImageMediaType, data: &'a str) -> Self {
+        ImageSource(Source::Base64 { media_type, data })
+    }
+
+    /// An image the API fetches itself.
+    pub fn new_url(url: &'a str) -> Self {
+        ImageSource(Source::Url { url })
+    }
+
+    /// The image's URL, or the image as a `data:` URL.
+    pub fn url(&self) -> String {
+        match &self.0 {
+            Source::Base64 { media_type, data } => {
+                format!("data:{};base64,{data}", media_type.mime_type())
+            }
+            Source::Url { url } => url.to_string(),
         }
     }
 }
 
+/// A PDF. The API doesn't take documents in other formats.
+#[derive(serde::Serialize)]
+#[serde(transparent)]
+pub struct DocumentSource<'a>(Source<'a, &'static str>);
+
+impl<'a> DocumentSource<'a> {
+    pub fn pdf_base64(data: &'a str) -> Self {
+        DocumentSource(Source::Base64 {
+            media_type: "application/pdf",
+            data,
+        })
+    }
+
+    /// A PDF the API fetches itself.
+    pub fn pdf_url(url: &'a str) -> Self {
+        DocumentSource(Source::Url { url })
+    }
+}
+
 #[derive(serde::Deserialize, Debug)]
 struct CompletionResponse {
     content: Vec<ResponseContent>,
+    stop_reason: Option<String>,
+    #[serde(default)]
+    usage: crate::usage::Usage,
 }
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(tag = "type")]
 enum ResponseContent {
-    #[serde(rename = "thinking")]
-    Thinking { thinking: String },
     #[serde(rename = "text")]
     Text { text: String },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
 }
 
-pub async fn get_completion(
-    client: &reqwest::Client,
-    api_token: &str,
-    message_contents: impl IntoIterator<Item = MessageContent<'_>>,
-) -> anyhow::Result<String> {
+async fn send_request(
+    client: &Client,
+    request: &CompletionRequest<'_>,
+) -> Result<reqwest::Response, AnthropicError> {
     let response = client
-        .post("https://api.anthropic.com/v1/messages")
-        .header("x-api-key", api_token)
+        .http
+        .post(format!(
+            "{}/v1/messages",
+            client.base_url.trim_end_matches('/')
+        ))
+        .header("x-api-key", &*client.api_token)
         .header("anthropic-version", "2023-06-01")
-        .json(&CompletionRequest {
-            model: "claude-fable-5",
-            messages: vec![CompletionRequestMessage {
-                role: "user",
-                content: message_contents.into_iter().collect(),
-            }],
-            max_tokens: 16000,
-            thinking: Thinking { type_: "adaptive" },
-        })
+        .json(request)
         .send()
+        .await
+        .map_err(AnthropicError::Http)?;
+    if !response.status().is_success() {
+        return Err(AnthropicError::from_response(response).await);
+    }
+    Ok(response)
+}
+
+/// Ask for a completion of a single user message.
+pub async fn get_completion(
+    client: &Client,
+    options: &CompletionOptions,
+    message_contents: impl IntoIterator<Item = MessageContent<'_>>,
+) -> Result<String, AnthropicError> {
+    complete(client, options, &Conversation::new().user(message_contents)).await
+}
+
+/// Send a request, retrying rate limits, overloads and server errors with
+/// backoff.
+async fn post(
+    client: &Client,
+    request: &CompletionRequest<'_>,
+) -> Result<reqwest::Response, AnthropicError> {
+    client.check_budget().await?;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match send_request(client, request).await {
+            Ok(response) => return Ok(response),
+            Err(err) if err.is_transient() && attempt < MAX_ATTEMPTS => {
+                let delay = err
+                    .retry_after()
+                    .unwrap_or(std::time::Duration::from_secs(1 << attempt))
+                    .min(MAX_RETRY_DELAY);
+                tracing::warn!(attempt, ?delay, error = %err, "Retrying Anthropic API request");
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn send(
+    client: &Client,
+    request: &CompletionRequest<'_>,
+) -> Result<CompletionResponse, AnthropicError> {
+    let response: CompletionResponse = post(client, request)
         .await?
-        .json::<CompletionResponse>()
-        .await?;
+        .json()
+        .await
+        .map_err(AnthropicError::Http)?;
+    client.record_usage(request.model, response.usage).await;
+    if response.stop_reason.as_deref() == Some("refusal") {
+        return Err(AnthropicError::Refusal);
+    }
+    Ok(response)
+}
+
+/// Ask for the next assistant turn in `conversation`.
+pub async fn complete(
+    client: &Client,
+    options: &CompletionOptions,
+    conversation: &Conversation<'_>,
+) -> Result<String, AnthropicError> {
+    let request = CompletionRequest {
+        model: &options.model,
+        system: &conversation.system,
+        messages: &conversation.messages,
+        max_tokens: options.max_tokens,
+        thinking: options.thinking,
+        temperature: options.temperature,
+        stop_sequences: &options.stop_sequences,
+        tools: &[],
+        tool_choice: None,
+        stream: false,
+    };
 
-    Ok(response
+    let response = send(client, &request).await?;
+    response
         .content
         .into_iter()
         .find_map(|c| match c {
             ResponseContent::Text { text } => Some(text),
             _ => None,
         })
-        .expect("No text content in response"))
+        .ok_or(AnthropicError::EmptyContent {
+            stop_reason: response.stop_reason,
+        })
+}
+
+/// Make the model call `tool` and return its input, e.g. to get structured
+/// output matching the tool's schema.
+///
+/// Thinking is always disabled, since the API doesn't allow it when the
+/// tool choice is forced.
+pub async fn call_tool<T: serde::de::DeserializeOwned>(
+    client: &Client,
+    options: &CompletionOptions,
+    conversation: &Conversation<'_>,
+    tool: &Tool<'_>,
+) -> Result<T, AnthropicError> {
+    let request = CompletionRequest {
+        model: &options.model,
+        system: &conversation.system,
+        messages: &conversation.messages,
+        max_tokens: options.max_tokens,
+        thinking: Thinking::Disabled,
+        temperature: options.temperature,
+        stop_sequences: &options.stop_sequences,
+        tools: std::slice::from_ref(tool),
+        tool_choice: Some(ToolChoice::Tool { name: tool.name }),
+        stream: false,
+    };
+
+    let response = send(client, &request).await?;
+    let input = response
+        .content
+        .into_iter()
+        .find_map(|c| match c {
+            ResponseContent::ToolUse { name, input } if name == tool.name => Some(input),
+            _ => None,
+        })
+        .ok_or(AnthropicError::EmptyContent {
+            stop_reason: response.stop_reason,
+        })?;
+    serde_json::from_value(input).map_err(|source| AnthropicError::InvalidToolInput {
+        tool: tool.name.to_string(),
+        source,
+    })
+}
+
+#[derive(Debug)]
+pub enum StreamEvent {
+    Text(String),
+    Thinking(String),
+}
+
+/// The server-sent events of a streamed response, read with
+/// [CompletionStream::next].
+pub struct CompletionStream {
+    client: Client,
+    model: String,
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+    done: bool,
+    usage: crate::usage::Usage,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SseEvent {
+    MessageStart {
+        message: SseMessageStart,
+    },
+    ContentBlockDelta {
+        delta: SseDelta,
+    },
+    MessageDelta {
+        delta: SseMessageDelta,
+        #[serde(default)]
+        usage: SseDeltaUsage,
+    },
+    MessageStop,
+    Error {
+        error: ErrorDetail,
+    },
+    // content_block_start/stop and ping.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SseDelta {
+    TextDelta {
+        text: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    // Thinking signatures and tool input.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(serde::Deserialize)]
+struct SseMessageStart {
+    usage: crate::usage::Usage,
+}
+
+#[derive(serde::Deserialize)]
+struct SseMessageDelta {
+    stop_reason: Option<String>,
+}
+
+/// Output tokens so far. Input tokens are only counted in message_start.
+#[derive(serde::Deserialize, Default)]
+struct SseDeltaUsage {
+    output_tokens: Option<u64>,
+}
+
+impl CompletionStream {
+    /// The `data` of the next event, or `None` if the response has ended.
+    async fn next_data(&mut self) -> Result<Option<String>, AnthropicError> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+                let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                let block = String::from_utf8_lossy(&block);
+                let data: Vec<&str> = block
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(str::trim_start)
+                    .collect();
+                if !data.is_empty() {
+                    return Ok(Some(data.join("\n")));
+                }
+                continue;
+            }
+            match self.response.chunk().await.map_err(AnthropicError::Http)? {
+                Some(chunk) => self
+                    .buffer
+                    .extend(chunk.iter().copied().filter(|&b| b != b'\r')),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The next text or thinking delta, or `None` once the message is
+    /// complete.
+    pub async fn next(&mut self) -> Option<Result<StreamEvent, AnthropicError>> {
+        while !self.done {
+            let result = match self.next_data().await {
+                Ok(Some(data)) => serde_json::from_str::<SseEvent>(&data).map_err(|err| {
+                    tracing::debug!(data, error = %err, "Unparseable event in response stream");
+                }),
+                Ok(None) => {
+                    self.done = true;
+                    return Some(Err(AnthropicError::IncompleteStream));
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+            match result {
+                Ok(SseEvent::MessageStart { message }) => self.usage = message.usage,
+                Ok(SseEvent::ContentBlockDelta {
+                    delta: SseDelta::TextDelta { text },
+                }) => return Some(Ok(StreamEvent::Text(text))),
+                Ok(SseEvent::ContentBlockDelta {
+                    delta: SseDelta::ThinkingDelta { thinking },
+                }) => return Some(Ok(StreamEvent::Thinking(thinking))),
+                Ok(SseEvent::MessageDelta { delta, usage }) => {
+                    if let Some(output_tokens) = usage.output_tokens {
+                        self.usage.output_tokens = output_tokens;
+                    }
+                    if delta.stop_reason.as_deref() == Some("refusal") {
+                        self.done = true;
+                        self.client.record_usage(&self.model, self.usage).await;
+                        return Some(Err(AnthropicError::Refusal));
+                    }
+                }
+                Ok(SseEvent::MessageStop) => {
+                    self.done = true;
+                    self.client.record_usage(&self.model, self.usage).await;
+                }
+                Ok(SseEvent::Error { error }) => {
+                    self.done = true;
+                    return Some(Err(AnthropicError::from_stream(error)));
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Wait for the whole response and return its text.
+    pub async fn text(mut self) -> Result<String, AnthropicError> {
+        let mut text = String::new();
+        while let Some(event) = self.next().await {
+            if let StreamEvent::Text(delta) = event? {
+                text.push_str(&delta);
+            }
+        }
+        Ok(text)
+    }
+}
+
+/// Like [complete], but returns as soon as the response starts, so the text
+/// can be read as it's generated. Long responses should be streamed so the
+/// connection doesn't sit idle until they finish.
+pub async fn stream_completion(
+    client: &Client,
+    options: &CompletionOptions,
+    conversation: &Conversation<'_>,
+) -> Result<CompletionStream, AnthropicError> {
+    let request = CompletionRequest {
+        model: &options.model,
+        system: &conversation.system,
+        messages: &conversation.messages,
+        max_tokens: options.max_tokens,
+        thinking: options.thinking,
+        temperature: options.temperature,
+        stop_sequences: &options.stop_sequences,
+        tools: &[],
+        tool_choice: None,
+        stream: true,
+    };
+    Ok(CompletionStream {
+        client: client.clone(),
+        model: options.model.clone(),
+        response: post(client, &request).await?,
+        buffer: Vec::new(),
+        done: false,
+        usage: Default::default(),
+    })
+}
+
+impl crate::llm::LlmProvider for Client {
+    fn name(&self) -> &str {
+        crate::llm::ANTHROPIC
+    }
+
+    // Streamed, so a long response (e.g. with thinking) doesn't leave the
+    // connection idle until it's done.
+    fn complete<'a>(
+        &'a self,
+        options: &'a CompletionOptions,
+        conversation: &'a Conversation<'a>,
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        self.stream(options, conversation, &|_| {})
+    }
+
+    fn stream<'a>(
+        &'a self,
+        options: &'a CompletionOptions,
+        conversation: &'a Conversation<'a>,
+        on_text: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let mut stream = stream_completion(self, options, conversation).await?;
+            let mut text = String::new();
+            while let Some(event) = stream.next().await {
+                if let StreamEvent::Text(delta) = event? {
+                    on_text(&delta);
+                    text.push_str(&delta);
+                }
+            }
+            Ok(text)
+        })
+    }
 }
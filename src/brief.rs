@@ -0,0 +1,91 @@
+pub struct Brief {
+    pub date: chrono::DateTime<chrono::Local>,
+    pub weather: Vec<(String, String)>,
+    pub todo_items: Vec<crate::todoist::TodoItem>,
+    /// `None` when it couldn't be had, e.g. because the LLM budget is used
+    /// up.
+    pub us_history_fact: Option<String>,
+}
+
+const FACT_HEADING: &str = "US History Fact:";
+
+/// Everything before the history fact, without cutting the paper.
+fn brief_head(brief: &Brief) -> crate::receipt::Receipt {
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("Alex's Daily Brief")
+        .centered(brief.date.format("%A %B %d, %Y").to_string())
+        .feed(2)
+        .heading("Weather Forecast:");
+    for (day, forecast) in &brief.weather {
+        receipt = receipt.paragraph(format!("{}: {}", day, forecast));
+    }
+
+    receipt.feed(2).heading("TODO:").checklist(
+        brief
+            .todo_items
+            .iter()
+            .map(|todo| crate::receipt::ChecklistItem {
+                highlight: todo.time.map(|t| t.format("%-I:%M %p").to_string()),
+                text: todo.content.clone(),
+                link: todo.url.clone(),
+            })
+            .collect(),
+    )
+}
+
+pub fn brief_receipt(brief: &Brief) -> crate::receipt::Receipt {
+    let mut receipt = brief_head(brief);
+    if let Some(fact) = &brief.us_history_fact {
+        receipt = receipt.feed(2).heading(FACT_HEADING).paragraph(fact);
+    }
+
+    receipt.feed(5).cut()
+}
+
+/// Print `brief` with the history fact arriving over `fact` a piece at a
+/// time, as it's generated. Everything else prints straight away and each
+/// line of the fact as soon as it's complete, so the result looks the same
+/// as [brief_receipt]. `brief.us_history_fact` is ignored.
+pub async fn print_streaming(
+    printers: &[crate::printer::Printer],
+    brief: &Brief,
+    mut fact: tokio::sync::mpsc::UnboundedReceiver<String>,
+) -> anyhow::Result<()> {
+    let head = brief_head(brief);
+    for printer in printers {
+        printer.print_receipt(&head).await?;
+    }
+
+    let mut breakers: Vec<_> = printers
+        .iter()
+        .map(|printer| crate::receipt::LineBreaker::new(printer.config().paper_width.columns()))
+        .collect();
+    let mut started = false;
+    while let Some(delta) = fact.recv().await {
+        if delta.is_empty() {
+            continue;
+        }
+        for (printer, breaker) in printers.iter().zip(&mut breakers) {
+            let mut receipt = crate::receipt::Receipt::new();
+            if !started {
+                receipt = receipt.feed(2).heading(FACT_HEADING);
+            }
+            for line in breaker.push(&delta) {
+                receipt = receipt.paragraph(line);
+            }
+            if !receipt.elements().is_empty() {
+                printer.print_receipt(&receipt).await?;
+            }
+        }
+        started = true;
+    }
+
+    for (printer, breaker) in printers.iter().zip(breakers) {
+        let mut receipt = crate::receipt::Receipt::new();
+        for line in breaker.finish() {
+            receipt = receipt.paragraph(line);
+        }
+        printer.print_receipt(&receipt.feed(5).cut()).await?;
+    }
+    Ok(())
+}
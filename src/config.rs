@@ -0,0 +1,144 @@
+use anyhow::Context;
+
+/// The name given to `[printer]` when no `[printers.<name>]` are configured.
+pub const DEFAULT_PRINTER: &str = "default";
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// The only printer, unless `printers` is set.
+    pub printer: crate::printer::PrinterConfig,
+    /// Named printers. Overrides `printer` when non-empty.
+    pub printers: std::collections::BTreeMap<String, crate::printer::PrinterConfig>,
+    pub routes: Routes,
+    pub completions: Completions,
+    /// LLM servers besides Anthropic, by name.
+    pub llm_providers: std::collections::BTreeMap<String, crate::llm::ProviderConfig>,
+    pub llm_routes: LlmRoutes,
+    pub usage: crate::usage::UsageConfig,
+    pub courtlistener: crate::courtlistener::WebhookConfig,
+    /// Where the gram server keeps jobs that haven't printed yet. Each
+    /// printer gets its own subdirectory.
+    pub queue_dir: std::path::PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            printer: Default::default(),
+            printers: Default::default(),
+            routes: Default::default(),
+            completions: Default::default(),
+            llm_providers: Default::default(),
+            llm_routes: Default::default(),
+            usage: Default::default(),
+            courtlistener: Default::default(),
+            queue_dir: "print-queue".into(),
+        }
+    }
+}
+
+/// Which printers each kind of job goes to. A job kind that isn't listed
+/// goes to the printer named `default`.
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Routes {
+    pub brief: Option<Vec<String>>,
+    pub gram: Option<Vec<String>>,
+    pub court: Option<Vec<String>>,
+}
+
+/// Which LLM providers each kind of job tries, in order, until one works.
+/// A job kind that isn't listed only uses Anthropic.
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct LlmRoutes {
+    pub brief: Option<Vec<String>>,
+    pub gram: Option<Vec<String>>,
+}
+
+/// Model settings for each place we ask for a completion.
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Completions {
+    pub history_fact: crate::anthropic::CompletionOptions,
+    pub gram_description: crate::anthropic::CompletionOptions,
+    pub docket_classifier: crate::anthropic::CompletionOptions,
+}
+
+impl Default for Completions {
+    fn default() -> Self {
+        Completions {
+            history_fact: Default::default(),
+            gram_description: Default::default(),
+            // It only has to fill in a tool call, with a sentence per entry.
+            docket_classifier: crate::anthropic::CompletionOptions::new()
+                .max_tokens(4096)
+                .thinking(crate::anthropic::Thinking::Disabled)
+                .temperature(0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum JobKind {
+    Brief,
+    Gram,
+    Court,
+}
+
+impl Config {
+    pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Error reading config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Error parsing config file {}", path.display()))
+    }
+
+    /// Every configured printer, by name.
+    pub fn printers(&self) -> std::collections::BTreeMap<String, crate::printer::PrinterConfig> {
+        if self.printers.is_empty() {
+            [(DEFAULT_PRINTER.to_string(), self.printer.clone())].into()
+        } else {
+            self.printers.clone()
+        }
+    }
+
+    /// The names of the printers that `kind` jobs should print on.
+    pub fn route(&self, kind: JobKind) -> anyhow::Result<Vec<String>> {
+        let route = match kind {
+            JobKind::Brief => &self.routes.brief,
+            JobKind::Gram => &self.routes.gram,
+            JobKind::Court => &self.routes.court,
+        };
+        let names = route
+            .clone()
+            .unwrap_or_else(|| vec![DEFAULT_PRINTER.to_string()]);
+        let printers = self.printers();
+        if let Some(name) = names.iter().find(|name| !printers.contains_key(*name)) {
+            anyhow::bail!("Route for {kind:?} jobs refers to unknown printer {name:?}");
+        }
+        Ok(names)
+    }
+
+    /// The names of the LLM providers that `kind` jobs should try, in order.
+    pub fn llm_route(&self, kind: JobKind) -> anyhow::Result<Vec<String>> {
+        let route = match kind {
+            JobKind::Brief => &self.llm_routes.brief,
+            JobKind::Gram => &self.llm_routes.gram,
+            // The docket classifier needs tool use, which only the Anthropic
+            // client does.
+            JobKind::Court => &None,
+        };
+        let names = route
+            .clone()
+            .unwrap_or_else(|| vec![crate::llm::ANTHROPIC.to_string()]);
+        if let Some(name) = names
+            .iter()
+            .find(|name| *name != crate::llm::ANTHROPIC && !self.llm_providers.contains_key(*name))
+        {
+            anyhow::bail!("LLM route for {kind:?} jobs refers to unknown provider {name:?}");
+        }
+        Ok(names)
+    }
+}
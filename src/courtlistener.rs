@@ -1,38 +1,454 @@
-use epson::AsyncWriterExt;
-
 // CourtListener webhook structures
-#[derive(serde::Deserialize, Debug)]
+#[derive(Debug)]
 pub struct CourtListenerWebhook {
-    payload: WebhookPayload,
+    pub webhook: WebhookMetadata,
+    pub event: WebhookEvent,
+}
+
+/// A delivery before its payload is read, which depends on the event type.
+#[derive(serde::Deserialize)]
+struct RawWebhook {
+    webhook: WebhookMetadata,
+    #[serde(default)]
+    payload: serde_json::Value,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct WebhookMetadata {
+    pub version: u32,
+    /// Missing from older deliveries, which were all docket alerts.
+    #[serde(default)]
+    pub event_type: EventType,
+}
+
+/// CourtListener numbers its event types. Its "test" button sends an
+/// ordinary delivery of the endpoint's type, filled with sample data, so
+/// there's no type for tests.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(from = "u32")]
+pub enum EventType {
+    #[default]
+    DocketAlert,
+    SearchAlert,
+    RecapFetch,
+    OldDocketAlertsReport,
+    /// A type added since this was written. These are accepted, so
+    /// CourtListener doesn't keep retrying them and disable the endpoint.
+    Unknown(u32),
+}
+
+impl From<u32> for EventType {
+    fn from(code: u32) -> Self {
+        match code {
+            1 => EventType::DocketAlert,
+            2 => EventType::SearchAlert,
+            3 => EventType::RecapFetch,
+            4 => EventType::OldDocketAlertsReport,
+            code => EventType::Unknown(code),
+        }
+    }
+}
+
+/// A delivery's payload.
+#[derive(Debug)]
+pub enum WebhookEvent {
+    /// New entries on dockets with an alert.
+    DocketAlert(WebhookPayload),
+    /// New results for a saved search.
+    SearchAlert(SearchAlert),
+    /// A request to fetch something from PACER has finished.
+    RecapFetch(RecapFetch),
+    /// Docket alerts that haven't fired in a long time, some of which
+    /// CourtListener has turned off.
+    OldDocketAlertsReport(OldDocketAlertsReport),
+    /// Logged and otherwise ignored.
+    Unknown(u32),
+}
+
+impl WebhookEvent {
+    fn from_payload(
+        event_type: EventType,
+        payload: serde_json::Value,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(match event_type {
+            EventType::DocketAlert => WebhookEvent::DocketAlert(serde_json::from_value(payload)?),
+            EventType::SearchAlert => WebhookEvent::SearchAlert(serde_json::from_value(payload)?),
+            EventType::RecapFetch => WebhookEvent::RecapFetch(serde_json::from_value(payload)?),
+            EventType::OldDocketAlertsReport => {
+                WebhookEvent::OldDocketAlertsReport(serde_json::from_value(payload)?)
+            }
+            EventType::Unknown(code) => WebhookEvent::Unknown(code),
+        })
+    }
+
+    /// For logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::DocketAlert(_) => "docket alert",
+            WebhookEvent::SearchAlert(_) => "search alert",
+            WebhookEvent::RecapFetch(_) => "RECAP fetch",
+            WebhookEvent::OldDocketAlertsReport(_) => "old docket alerts report",
+            WebhookEvent::Unknown(_) => "unknown event",
+        }
+    }
+
+    /// How many things the delivery is about, for the delivery log.
+    fn len(&self) -> usize {
+        match self {
+            WebhookEvent::DocketAlert(payload) => payload.results.len(),
+            WebhookEvent::SearchAlert(alert) => alert.results.len(),
+            WebhookEvent::RecapFetch(_) => 1,
+            WebhookEvent::Unknown(_) => 0,
+            WebhookEvent::OldDocketAlertsReport(report) => {
+                report.old_alerts.len()
+                    + report.very_old_alerts.len()
+                    + report.disabled_alerts.len()
+            }
+        }
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct WebhookPayload {
-    results: Vec<DocketEntry>,
+    pub results: Vec<DocketEntry>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct DocketEntry {
-    description: Option<String>,
-    entry_number: Option<i32>,
-    date_filed: Option<String>,
-    recap_documents: Option<Vec<RecapDocument>>,
+    pub id: Option<i64>,
+    pub docket: Option<i64>,
+    pub case_name: Option<String>,
+    /// The court's CourtListener ID, e.g. `dcd`.
+    #[serde(alias = "court_id")]
+    pub court: Option<String>,
+    pub docket_number: Option<String>,
+    pub description: Option<String>,
+    pub entry_number: Option<i32>,
+    pub date_filed: Option<String>,
+    pub recap_documents: Option<Vec<RecapDocument>>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct RecapDocument {
-    description: Option<String>,
-    document_number: Option<String>,
+    pub description: Option<String>,
+    pub document_number: Option<String>,
+    pub page_count: Option<u32>,
+    /// The document's page on CourtListener.
+    pub absolute_url: Option<String>,
+    /// The PDF, relative to [COURTLISTENER_STORAGE_URL].
+    pub filepath_local: Option<String>,
 }
 
-async fn check_if_substantive(
-    client: &reqwest::Client,
-    api_token: &str,
-    entry: &DocketEntry,
-) -> anyhow::Result<bool> {
-    // Build a description of the docket entry
-    let entry_description = format!(
-        "Docket Entry:\n\
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SearchAlert {
+    pub alert: SavedSearch,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct SavedSearch {
+    pub name: Option<String>,
+    pub query: Option<String>,
+}
+
+/// A hit from CourtListener's search API, which names its fields
+/// differently from the REST API.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub case_name: Option<String>,
+    /// The court's full name.
+    pub court: Option<String>,
+    pub docket_number: Option<String>,
+    pub date_filed: Option<String>,
+    /// Matching text, with matches wrapped in `<mark>`.
+    pub snippet: Option<String>,
+    #[serde(rename = "absolute_url")]
+    pub absolute_url: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct RecapFetch {
+    pub id: Option<i64>,
+    /// CourtListener's processing status; 2 is success and 3 failure.
+    pub status: Option<u32>,
+    pub message: Option<String>,
+    pub court: Option<String>,
+    pub docket: Option<i64>,
+    pub recap_document: Option<i64>,
+    pub date_completed: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct OldDocketAlertsReport {
+    #[serde(default)]
+    pub old_alerts: Vec<StaleDocketAlert>,
+    #[serde(default)]
+    pub very_old_alerts: Vec<StaleDocketAlert>,
+    #[serde(default)]
+    pub disabled_alerts: Vec<StaleDocketAlert>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct StaleDocketAlert {
+    pub docket: Option<i64>,
+    pub date_last_hit: Option<String>,
+}
+
+/// The webhook payload versions we know how to read.
+const SUPPORTED_WEBHOOK_VERSIONS: &[u32] = &[1, 2];
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Where to record deliveries and what was printed from them.
+    pub delivery_log: std::path::PathBuf,
+    /// Only accept deliveries from these addresses. Empty accepts any.
+    pub allowed_ips: Vec<std::net::IpAddr>,
+    /// Take the source address from the last `X-Forwarded-For` entry, as
+    /// added by a reverse proxy. Only set this behind a proxy, since anyone
+    /// can send the header.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            delivery_log: "courtlistener-deliveries.jsonl".into(),
+            allowed_ips: Vec::new(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WebhookRejection {
+    ForbiddenSource(std::net::IpAddr),
+    MissingIdempotencyKey,
+    InvalidIdempotencyKey,
+    Malformed(serde_json::Error),
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for WebhookRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookRejection::ForbiddenSource(ip) => {
+                write!(f, "Webhook deliveries aren't accepted from {ip}")
+            }
+            WebhookRejection::MissingIdempotencyKey => write!(f, "Missing Idempotency-Key header"),
+            WebhookRejection::InvalidIdempotencyKey => write!(f, "Invalid Idempotency-Key header"),
+            WebhookRejection::Malformed(err) => write!(f, "Malformed webhook payload: {err}"),
+            WebhookRejection::UnsupportedVersion(version) => {
+                write!(f, "Unsupported webhook version {version}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WebhookRejection {}
+
+/// A delivery that passed [WebhookGuard::check].
+#[derive(Debug)]
+pub struct Delivery {
+    pub idempotency_key: String,
+    pub webhook: CourtListenerWebhook,
+}
+
+/// Checks webhook deliveries before anything is done with them.
+pub struct WebhookGuard {
+    config: WebhookConfig,
+}
+
+impl WebhookGuard {
+    pub fn new(config: WebhookConfig) -> Self {
+        WebhookGuard { config }
+    }
+
+    /// The address a delivery came from.
+    fn source(&self, peer: std::net::IpAddr, forwarded_for: Option<&str>) -> std::net::IpAddr {
+        if self.config.trust_forwarded_for
+            && let Some(ip) = forwarded_for
+                .and_then(|v| v.rsplit(',').next())
+                .and_then(|ip| ip.trim().parse().ok())
+        {
+            return ip;
+        }
+        peer
+    }
+
+    pub fn check(
+        &self,
+        peer: std::net::IpAddr,
+        forwarded_for: Option<&str>,
+        idempotency_key: Option<&str>,
+        body: &[u8],
+    ) -> Result<Delivery, WebhookRejection> {
+        let source = self.source(peer, forwarded_for);
+        if !self.config.allowed_ips.is_empty() && !self.config.allowed_ips.contains(&source) {
+            return Err(WebhookRejection::ForbiddenSource(source));
+        }
+
+        let key = idempotency_key.ok_or(WebhookRejection::MissingIdempotencyKey)?;
+        if key.is_empty() || key.len() > 128 || !key.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(WebhookRejection::InvalidIdempotencyKey);
+        }
+
+        let RawWebhook { webhook, payload } =
+            serde_json::from_slice(body).map_err(WebhookRejection::Malformed)?;
+        if !SUPPORTED_WEBHOOK_VERSIONS.contains(&webhook.version) {
+            return Err(WebhookRejection::UnsupportedVersion(webhook.version));
+        }
+        let event = WebhookEvent::from_payload(webhook.event_type, payload)
+            .map_err(WebhookRejection::Malformed)?;
+
+        Ok(Delivery {
+            idempotency_key: key.to_string(),
+            webhook: CourtListenerWebhook { webhook, event },
+        })
+    }
+}
+
+const COURTLISTENER_URL: &str = "https://www.courtlistener.com";
+const COURTLISTENER_STORAGE_URL: &str = "https://storage.courtlistener.com";
+
+impl DocketEntry {
+    /// What identifies the entry across deliveries, if anything does.
+    pub fn key(&self) -> Option<String> {
+        match (self.id, self.docket, self.entry_number) {
+            (Some(id), _, _) => Some(format!("entry:{id}")),
+            (None, Some(docket), Some(n)) => Some(format!("docket:{docket}:{n}")),
+            _ => None,
+        }
+    }
+
+    /// A link to the entry on CourtListener: its first document if there is
+    /// one, otherwise the entry on the docket page.
+    fn url(&self) -> Option<String> {
+        if let Some(url) = self
+            .recap_documents
+            .iter()
+            .flatten()
+            .find_map(RecapDocument::url)
+        {
+            return Some(url);
+        }
+        let docket = self.docket?;
+        Some(match self.entry_number {
+            Some(n) => format!("{COURTLISTENER_URL}/docket/{docket}/#entry-{n}"),
+            None => format!("{COURTLISTENER_URL}/docket/{docket}/"),
+        })
+    }
+}
+
+impl RecapDocument {
+    /// The document's page, or failing that the PDF itself.
+    fn url(&self) -> Option<String> {
+        if let Some(path) = &self.absolute_url {
+            return Some(format!("{COURTLISTENER_URL}{path}"));
+        }
+        let path = self.filepath_local.as_deref()?;
+        Some(format!(
+            "{COURTLISTENER_STORAGE_URL}/{}",
+            path.trim_start_matches('/')
+        ))
+    }
+}
+
+const SUBSTANTIVE_SYSTEM_PROMPT: &str = "You are analyzing a court docket filing to determine if it is substantive. \
+    A substantive filing is one that materially affects the case, such as: motions, \
+    orders, opinions, judgments, briefs, complaints, answers, or other significant \
+    legal documents. Non-substantive filings include: notices of appearance, \
+    administrative orders, scheduling updates, certificate of service, \
+    motions and orders regarding pro hac vice, motions and orders \
+    regarding leave to file amicus curiae brief, and filings with vague or generic \
+    descriptions that provide no meaningful detail about the content (e.g., 'reply \
+    in support of motion', 'memorandum in opposition', 'brief in support', etc. \
+    without further specifics).\n\n\
+    The user will send one or more numbered filings. Classify every one of them \
+    in a single call to the classify_filings tool.";
+
+/// How many entries to classify per request. Bigger webhooks are split up so
+/// the verdicts fit in the response.
+const CLASSIFY_CHUNK_SIZE: usize = 20;
+
+/// The classifier's verdict on a docket entry.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Classification {
+    pub substantive: bool,
+    pub category: String,
+    /// A sentence explaining the verdict.
+    pub reason: String,
+}
+
+impl Classification {
+    /// Used when the classifier couldn't say. Better to print everything
+    /// than to miss a filing that matters.
+    fn unclassified(reason: String) -> Self {
+        Classification {
+            substantive: true,
+            category: "unclassified".to_string(),
+            reason,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct ClassifyFilingsInput {
+    classifications: Vec<NumberedClassification>,
+}
+
+#[derive(serde::Deserialize)]
+struct NumberedClassification {
+    filing: usize,
+    #[serde(flatten)]
+    classification: Classification,
+}
+
+fn classify_filings_tool() -> crate::anthropic::Tool<'static> {
+    crate::anthropic::Tool {
+        name: "classify_filings",
+        description: "Record whether each docket filing is substantive, and why.",
+        input_schema: serde_json::json!({
+            "type": "object",
+            "properties": {
+                "classifications": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "filing": {
+                                "type": "integer",
+                                "description": "The number of the filing, as given by the user.",
+                            },
+                            "substantive": {
+                                "type": "boolean",
+                                "description": "Whether the filing materially affects the case.",
+                            },
+                            "category": {
+                                "type": "string",
+                                "enum": [
+                                    "motion", "order", "opinion", "judgment", "brief", "pleading",
+                                    "notice", "scheduling", "administrative", "other",
+                                ],
+                            },
+                            "reason": {
+                                "type": "string",
+                                "description": "One short sentence explaining the classification.",
+                            },
+                        },
+                        "required": ["filing", "substantive", "category", "reason"],
+                    },
+                },
+            },
+            "required": ["classifications"],
+        }),
+    }
+}
+
+fn describe_entry(n: usize, entry: &DocketEntry) -> String {
+    format!(
+        "Filing {n}:\n\
          Entry Number: {}\n\
          Date Filed: {}\n\
          Description: {}\n\
@@ -52,159 +468,484 @@ async fn check_if_substantive(
                 .collect::<Vec<_>>()
                 .join("; "))
             .unwrap_or_else(|| "N/A".to_string())
-    );
-
-    let prompt = format!(
-        "You are analyzing a court docket filing to determine if it is substantive. \
-        A substantive filing is one that materially affects the case, such as: motions, \
-        orders, opinions, judgments, briefs, complaints, answers, or other significant \
-        legal documents. Non-substantive filings include: notices of appearance, \
-        administrative orders, scheduling updates, certificate of service, \
-        motions and orders regarding pro hac vice, motions and orders \
-        regarding leave to file amicus curiae brief, and filings with vague or generic \
-        descriptions that provide no meaningful detail about the content (e.g., 'reply \
-        in support of motion', 'memorandum in opposition', 'brief in support', etc. \
-        without further specifics).\n\n\
-        Analyze this filing and respond with ONLY 'YES' if it is substantive or 'NO' if it is not.\n\n\
-        {}",
-        entry_description
-    );
-
-    let response = crate::anthropic::get_completion(
-        client,
-        api_token,
-        [crate::anthropic::MessageContent::Text { text: &prompt }],
     )
-    .await?;
-
-    // Check if response contains "YES" (case insensitive)
-    Ok(response.trim().to_uppercase().contains("YES"))
 }
 
-async fn print_docket_alerts(entries: &[DocketEntry]) -> anyhow::Result<()> {
-    let mut w = crate::printer::new_epson_writer().await?;
-    let now = chrono::offset::Local::now();
-
-    w.justify(epson::Alignment::Center).await?;
-    w.underline(true).await?;
-    w.write_all(b"COURT ALERT\n").await?;
-    w.underline(false).await?;
+/// Classify `entries`, a chunk at a time, returning a verdict for each in
+/// order. Entries in a chunk the classifier fails on are unclassified.
+pub async fn classify_entries(
+    client: &crate::anthropic::Client,
+    options: &crate::anthropic::CompletionOptions,
+    entries: &[DocketEntry],
+) -> Vec<Classification> {
+    let mut classifications = Vec::with_capacity(entries.len());
+    for chunk in entries.chunks(CLASSIFY_CHUNK_SIZE) {
+        let filings = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| describe_entry(i + 1, entry))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let result = crate::anthropic::call_tool::<ClassifyFilingsInput>(
+            client,
+            options,
+            &crate::anthropic::Conversation::new()
+                // The rubric and tool are the same for every request.
+                .cached_system(SUBSTANTIVE_SYSTEM_PROMPT)
+                .user([crate::anthropic::MessageContent::Text {
+                    text: &filings,
+                    cache_control: None,
+                }]),
+            &classify_filings_tool(),
+        )
+        .await;
+        let mut verdicts: Vec<Option<Classification>> = vec![None; chunk.len()];
+        match result {
+            Ok(input) => {
+                for numbered in input.classifications {
+                    match numbered
+                        .filing
+                        .checked_sub(1)
+                        .and_then(|i| verdicts.get_mut(i))
+                    {
+                        Some(verdict) => *verdict = Some(numbered.classification),
+                        None => tracing::warn!(
+                            filing = numbered.filing,
+                            "Classifier returned a verdict for a filing that wasn't sent"
+                        ),
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!("Error classifying docket entries: {err}");
+                verdicts.fill(Some(Classification::unclassified(format!(
+                    "Not classified: {err}."
+                ))));
+            }
+        }
+        classifications.extend(verdicts.into_iter().map(|verdict| {
+            verdict.unwrap_or_else(|| {
+                Classification::unclassified(
+                    "Not classified: the classifier skipped this filing.".to_string(),
+                )
+            })
+        }));
+    }
+    classifications
+}
 
+pub fn docket_alerts_receipt(
+    entries: &[(DocketEntry, Classification)],
+    now: chrono::DateTime<chrono::Local>,
+) -> crate::receipt::Receipt {
     let filing_word = if entries.len() == 1 {
         "Filing"
     } else {
         "Filings"
     };
-    w.write_all(format!("{} Substantive {} Detected\n", entries.len(), filing_word).as_bytes())
-        .await?;
-    w.feed(1).await?;
-    w.justify(epson::Alignment::Left).await?;
-
-    w.write_all(format!("Alert Time: {}\n", now.format("%B %d, %Y at %I:%M:%S %p")).as_bytes())
-        .await?;
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("COURT ALERT")
+        .centered(format!(
+            "{} Substantive {} Detected",
+            entries.len(),
+            filing_word
+        ))
+        .feed(1)
+        .paragraph(format!(
+            "Alert Time: {}",
+            now.format("%B %d, %Y at %I:%M:%S %p")
+        ));
 
     // Print each entry
-    for (i, entry) in entries.iter().enumerate() {
+    for (i, (entry, classification)) in entries.iter().enumerate() {
         if i > 0 {
-            w.feed(2).await?;
-            w.write_all(b"---\n").await?;
-            w.feed(1).await?;
+            receipt = receipt.feed(2).separator().feed(1);
         } else {
-            w.feed(1).await?;
+            receipt = receipt.feed(1);
+        }
+
+        // Alerts can cover several tracked cases, so lead with which one.
+        if let Some(case_name) = &entry.case_name {
+            receipt = receipt.title(case_name);
+        }
+        let court = [
+            entry.court.as_ref().map(|court| court.to_uppercase()),
+            entry.docket_number.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !court.is_empty() {
+            receipt = receipt.centered(court.join(" | "));
+        }
+        if entry.case_name.is_some() || !court.is_empty() {
+            receipt = receipt.feed(1);
         }
 
         if let Some(entry_num) = entry.entry_number {
-            w.underline(true).await?;
-            w.write_all(b"Entry Number:").await?;
-            w.underline(false).await?;
-            w.write_all(format!(" {}\n", entry_num).as_bytes()).await?;
+            receipt = receipt.field("Entry Number", entry_num.to_string());
         }
 
         if let Some(date_filed) = &entry.date_filed {
-            w.underline(true).await?;
-            w.write_all(b"Date Filed:").await?;
-            w.underline(false).await?;
-            w.write_all(format!(" {}\n", date_filed).as_bytes()).await?;
+            receipt = receipt.field("Date Filed", date_filed);
         }
 
         if let Some(description) = &entry.description {
-            w.feed(1).await?;
-            w.underline(true).await?;
-            w.write_all(b"Description:\n").await?;
-            w.underline(false).await?;
-            w.write_all(format!("{}\n", description).as_bytes()).await?;
+            receipt = receipt
+                .feed(1)
+                .heading("Description:")
+                .paragraph(description);
         }
 
         // Print document descriptions if available
-        if let Some(docs) = &entry.recap_documents {
-            if !docs.is_empty()
-                && docs
-                    .iter()
-                    .filter_map(|doc| doc.description.as_ref())
-                    .filter(|desc| !desc.trim().is_empty())
-                    .count()
-                    > 0
-            {
-                w.feed(1).await?;
-                w.underline(true).await?;
-                w.write_all(b"Documents:\n").await?;
-                w.underline(false).await?;
-
-                for doc in docs {
-                    if let Some(doc_desc) = &doc.description {
-                        if doc_desc.trim().is_empty() {
-                            continue;
-                        }
-                        let doc_num = doc.document_number.as_deref().unwrap_or("?");
-                        w.write_all(format!("- Doc {}: {}\n", doc_num, doc_desc).as_bytes())
-                            .await?;
+        if let Some(docs) = &entry.recap_documents
+            && !docs.is_empty()
+            && docs
+                .iter()
+                .filter_map(|doc| doc.description.as_ref())
+                .filter(|desc| !desc.trim().is_empty())
+                .count()
+                > 0
+        {
+            receipt = receipt.feed(1).heading("Documents:");
+
+            for doc in docs {
+                if let Some(doc_desc) = &doc.description {
+                    if doc_desc.trim().is_empty() {
+                        continue;
                     }
+                    let doc_num = doc.document_number.as_deref().unwrap_or("?");
+                    let pages = match doc.page_count {
+                        Some(1) => " (1 page)".to_string(),
+                        Some(n) => format!(" ({n} pages)"),
+                        None => String::new(),
+                    };
+                    receipt =
+                        receipt.paragraph(format!("- Doc {}: {}{}", doc_num, doc_desc, pages));
                 }
             }
         }
+
+        receipt = receipt
+            .feed(1)
+            .field("Category", &classification.category)
+            .heading("Why It Matters:")
+            .paragraph(&classification.reason);
+
+        if let Some(url) = entry.url() {
+            receipt = receipt.feed(1).qr(url);
+        }
+    }
+
+    receipt.feed(3).cut()
+}
+
+/// `snippet` without the `<mark>` tags around matches.
+fn plain_snippet(snippet: &str) -> String {
+    snippet.replace("<mark>", "").replace("</mark>", "")
+}
+
+pub fn search_alert_receipt(
+    alert: &SearchAlert,
+    now: chrono::DateTime<chrono::Local>,
+) -> crate::receipt::Receipt {
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("SEARCH ALERT")
+        .centered(alert.alert.name.as_deref().unwrap_or("Saved search"))
+        .feed(1)
+        .paragraph(format!(
+            "Alert Time: {}",
+            now.format("%B %d, %Y at %I:%M:%S %p")
+        ));
+    if let Some(query) = &alert.alert.query {
+        receipt = receipt.field("Query", query);
     }
 
-    w.feed(3).await?;
-    w.cut().await?;
+    for (i, result) in alert.results.iter().enumerate() {
+        if i > 0 {
+            receipt = receipt.feed(2).separator().feed(1);
+        } else {
+            receipt = receipt.feed(1);
+        }
 
+        receipt = receipt.heading(result.case_name.as_deref().unwrap_or("Untitled case"));
+        if let Some(court) = &result.court {
+            receipt = receipt.field("Court", court);
+        }
+        if let Some(docket_number) = &result.docket_number {
+            receipt = receipt.field("Docket", docket_number);
+        }
+        if let Some(date_filed) = &result.date_filed {
+            receipt = receipt.field("Date Filed", date_filed);
+        }
+        if let Some(snippet) = &result.snippet
+            && !snippet.trim().is_empty()
+        {
+            receipt = receipt.feed(1).paragraph(plain_snippet(snippet));
+        }
+        if let Some(path) = &result.absolute_url {
+            receipt = receipt.feed(1).qr(format!("{COURTLISTENER_URL}{path}"));
+        }
+    }
+
+    receipt.feed(3).cut()
+}
+
+pub fn recap_fetch_receipt(
+    fetch: &RecapFetch,
+    now: chrono::DateTime<chrono::Local>,
+) -> crate::receipt::Receipt {
+    let status = match fetch.status {
+        Some(2) => "Completed".to_string(),
+        Some(3) => "Failed".to_string(),
+        Some(status) => format!("Status {status}"),
+        None => "Finished".to_string(),
+    };
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("RECAP FETCH")
+        .centered(status)
+        .feed(1)
+        .paragraph(format!(
+            "Alert Time: {}",
+            now.format("%B %d, %Y at %I:%M:%S %p")
+        ));
+    if let Some(court) = &fetch.court {
+        receipt = receipt.field("Court", court.to_uppercase());
+    }
+    if let Some(date_completed) = &fetch.date_completed {
+        receipt = receipt.field("Completed", date_completed);
+    }
+    if let Some(message) = &fetch.message
+        && !message.trim().is_empty()
+    {
+        receipt = receipt.feed(1).paragraph(message);
+    }
+    if let Some(docket) = fetch.docket {
+        receipt = receipt
+            .feed(1)
+            .qr(format!("{COURTLISTENER_URL}/docket/{docket}/"));
+    }
+    receipt.feed(3).cut()
+}
+
+pub fn old_docket_alerts_receipt(
+    report: &OldDocketAlertsReport,
+    now: chrono::DateTime<chrono::Local>,
+) -> crate::receipt::Receipt {
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("DOCKET ALERTS REPORT")
+        .centered(now.format("%B %d, %Y").to_string())
+        .feed(1)
+        .paragraph(
+            "These dockets haven't changed in a long time. Disabled alerts need to be \
+             turned back on at courtlistener.com to keep printing.",
+        );
+    for (heading, alerts) in [
+        ("Disabled:", &report.disabled_alerts),
+        ("Very Old:", &report.very_old_alerts),
+        ("Old:", &report.old_alerts),
+    ] {
+        if alerts.is_empty() {
+            continue;
+        }
+        receipt = receipt.feed(1).heading(heading);
+        for alert in alerts {
+            let docket = alert
+                .docket
+                .map(|docket| format!("{COURTLISTENER_URL}/docket/{docket}/"))
+                .unwrap_or_else(|| "Unknown docket".to_string());
+            receipt = receipt.paragraph(match &alert.date_last_hit {
+                Some(date) => format!("- {docket} (last hit {date})"),
+                None => format!("- {docket} (never hit)"),
+            });
+        }
+    }
+    receipt.feed(3).cut()
+}
+
+async fn submit(
+    queues: &[std::sync::Arc<crate::queue::PrintQueue>],
+    receipt: &crate::receipt::Receipt,
+    priority: crate::queue::Priority,
+    event: &str,
+) -> anyhow::Result<()> {
+    for queue in queues {
+        let id = queue.submit_receipt(receipt, priority).await?;
+        tracing::info!(
+            printer = queue.name(),
+            job = id,
+            event,
+            "Queued court alert"
+        );
+    }
     Ok(())
 }
 
+/// Classify and print a delivery in the background. Returns `false`,
+/// without doing anything, if the delivery was handled before or is being
+/// handled now. It's only recorded as handled once it's been queued to
+/// print, so a retry of one that failed gets another go. Without a
+/// `client`, every docket entry is printed unclassified.
 pub async fn handle_webhook(
-    client: &reqwest::Client,
-    api_token: &'static str,
-    webhook: CourtListenerWebhook,
-) -> anyhow::Result<()> {
-    let client = client.clone();
+    client: Option<&crate::anthropic::Client>,
+    options: crate::anthropic::CompletionOptions,
+    queues: Vec<std::sync::Arc<crate::queue::PrintQueue>>,
+    log: std::sync::Arc<crate::delivery_log::DeliveryLog>,
+    delivery: Delivery,
+) -> anyhow::Result<bool> {
+    let Delivery {
+        idempotency_key,
+        webhook,
+    } = delivery;
+    if !log.start_delivery(&idempotency_key).await {
+        return Ok(false);
+    }
+
+    let client = client.cloned();
     // courtlistener has a 2 second timeout and talking to an LLM + printing on
     // a printer can take longer than that, so we spawn a background task.
     tokio::spawn(async move {
-        if let Err(err) = process_webhook(&client, api_token, webhook).await {
+        let entries = webhook.event.len();
+        let result = process_webhook(
+            client.as_ref(),
+            &options,
+            &queues,
+            &log,
+            &idempotency_key,
+            webhook,
+        )
+        .await;
+        let result = match result {
+            Ok(()) => log.record_delivery(&idempotency_key, entries).await,
+            Err(err) => {
+                log.release_delivery(&idempotency_key).await;
+                Err(err)
+            }
+        };
+        if let Err(err) = result {
             tracing::error!(error = ?err, "Failed to process CourtListener webhook");
         }
     });
 
-    Ok(())
+    Ok(true)
 }
 
 async fn process_webhook(
-    client: &reqwest::Client,
-    api_token: &'static str,
+    client: Option<&crate::anthropic::Client>,
+    options: &crate::anthropic::CompletionOptions,
+    queues: &[std::sync::Arc<crate::queue::PrintQueue>],
+    log: &crate::delivery_log::DeliveryLog,
+    idempotency_key: &str,
     webhook: CourtListenerWebhook,
 ) -> anyhow::Result<()> {
-    // Check all entries and collect substantive ones
-    let mut substantive_entries = Vec::new();
+    let event = webhook.event.name();
+    let now = chrono::offset::Local::now();
+    match webhook.event {
+        WebhookEvent::DocketAlert(payload) => {
+            process_docket_alert(client, options, queues, log, idempotency_key, payload).await
+        }
+        WebhookEvent::SearchAlert(alert) => {
+            if alert.results.is_empty() {
+                return Ok(());
+            }
+            let receipt = search_alert_receipt(&alert, now);
+            submit(queues, &receipt, crate::queue::Priority::Normal, event).await
+        }
+        WebhookEvent::RecapFetch(fetch) => {
+            let receipt = recap_fetch_receipt(&fetch, now);
+            submit(queues, &receipt, crate::queue::Priority::Normal, event).await
+        }
+        WebhookEvent::OldDocketAlertsReport(report) => {
+            let receipt = old_docket_alerts_receipt(&report, now);
+            submit(queues, &receipt, crate::queue::Priority::Low, event).await
+        }
+        WebhookEvent::Unknown(code) => {
+            tracing::warn!(
+                event_type = code,
+                "Ignoring CourtListener webhook with an unknown event type"
+            );
+            Ok(())
+        }
+    }
+}
 
-    for entry in webhook.payload.results {
-        if check_if_substantive(client, api_token, &entry).await? {
-            substantive_entries.push(entry);
+async fn process_docket_alert(
+    client: Option<&crate::anthropic::Client>,
+    options: &crate::anthropic::CompletionOptions,
+    queues: &[std::sync::Arc<crate::queue::PrintQueue>],
+    log: &crate::delivery_log::DeliveryLog,
+    idempotency_key: &str,
+    payload: WebhookPayload,
+) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    for entry in payload.results {
+        if let Some(key) = entry.key()
+            && log.seen_entry(&key).await
+        {
+            tracing::info!(
+                entry = entry.entry_number,
+                "Skipping docket entry that was already handled"
+            );
+            continue;
+        }
+        entries.push(entry);
+    }
+    if entries.is_empty() {
+        return Ok(());
+    }
+
+    let classifications = match client {
+        Some(client) => classify_entries(client, options, &entries).await,
+        None => {
+            vec![
+                Classification::unclassified("Not classified: no Anthropic API token.".to_string());
+                entries.len()
+            ]
         }
+    };
+    let classified: Vec<_> = entries.into_iter().zip(classifications).collect();
+    for (entry, classification) in &classified {
+        tracing::info!(
+            entry = entry.entry_number,
+            substantive = classification.substantive,
+            category = classification.category,
+            reason = classification.reason,
+            "Classified docket entry"
+        );
     }
 
     // Only print if there are substantive entries
-    if !substantive_entries.is_empty() {
-        print_docket_alerts(&substantive_entries).await?;
+    let substantive_entries: Vec<_> = classified
+        .iter()
+        .filter(|(_, classification)| classification.substantive)
+        .cloned()
+        .collect();
+    let printed = if substantive_entries.is_empty() {
+        Ok(())
+    } else {
+        let receipt = docket_alerts_receipt(&substantive_entries, chrono::offset::Local::now());
+        submit(
+            queues,
+            &receipt,
+            crate::queue::Priority::High,
+            "docket alert",
+        )
+        .await
+    };
+
+    for (entry, classification) in classified {
+        log.record_entry(crate::delivery_log::Record::Entry {
+            at: chrono::Utc::now(),
+            idempotency_key: idempotency_key.to_string(),
+            entry: entry.key(),
+            entry_number: entry.entry_number,
+            description: entry.description,
+            substantive: classification.substantive,
+            category: classification.category,
+            printed: classification.substantive && printed.is_ok(),
+        })
+        .await?;
     }
 
-    Ok(())
+    printed
 }
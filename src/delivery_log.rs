@@ -0,0 +1,238 @@
+//! A JSONL record of CourtListener webhook deliveries and what became of
+//! each docket entry in them, so retried deliveries and entries that show
+//! up again aren't classified and printed twice.
+
+use anyhow::Context;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    /// A delivery was accepted.
+    Delivery {
+        at: chrono::DateTime<chrono::Utc>,
+        idempotency_key: String,
+        entries: usize,
+    },
+    /// A docket entry from a delivery was classified. `entry` is `None` if
+    /// the entry had nothing to identify it by.
+    Entry {
+        at: chrono::DateTime<chrono::Utc>,
+        idempotency_key: String,
+        entry: Option<String>,
+        entry_number: Option<i32>,
+        description: Option<String>,
+        substantive: bool,
+        category: String,
+        printed: bool,
+    },
+}
+
+#[derive(Default)]
+struct Index {
+    deliveries: std::collections::HashSet<String>,
+    /// Deliveries being processed, which aren't recorded until they're done.
+    in_flight: std::collections::HashSet<String>,
+    /// Entries that were printed or found not to be substantive. Ones that
+    /// should have printed but didn't are tried again.
+    entries: std::collections::HashSet<String>,
+}
+
+impl Index {
+    fn add(&mut self, record: Record) {
+        match record {
+            Record::Delivery {
+                idempotency_key, ..
+            } => {
+                self.in_flight.remove(&idempotency_key);
+                self.deliveries.insert(idempotency_key);
+            }
+            Record::Entry {
+                entry,
+                substantive,
+                printed,
+                ..
+            } => {
+                if let Some(entry) = entry
+                    && (printed || !substantive)
+                {
+                    self.entries.insert(entry);
+                }
+            }
+        }
+    }
+}
+
+pub struct DeliveryLog {
+    path: std::path::PathBuf,
+    // Held while appending, so checks and records don't interleave.
+    index: tokio::sync::Mutex<Index>,
+}
+
+impl DeliveryLog {
+    pub fn open(path: impl Into<std::path::PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        truncate_torn_line(&path)?;
+        let mut index = Index::default();
+        for record in read(&path)? {
+            index.add(record);
+        }
+        Ok(DeliveryLog {
+            path,
+            index: tokio::sync::Mutex::new(index),
+        })
+    }
+
+    /// Claim a delivery for processing, unless it's been recorded or is
+    /// already being processed. Returns whether it's new. Claimed deliveries
+    /// are either recorded or released once processing ends.
+    pub async fn start_delivery(&self, idempotency_key: &str) -> bool {
+        let mut index = self.index.lock().await;
+        !index.deliveries.contains(idempotency_key)
+            && index.in_flight.insert(idempotency_key.to_string())
+    }
+
+    /// Release a delivery that couldn't be processed, so a retry of it is
+    /// treated as new.
+    pub async fn release_delivery(&self, idempotency_key: &str) {
+        self.index.lock().await.in_flight.remove(idempotency_key);
+    }
+
+    /// Record a delivery that's been processed.
+    pub async fn record_delivery(
+        &self,
+        idempotency_key: &str,
+        entries: usize,
+    ) -> anyhow::Result<()> {
+        let mut index = self.index.lock().await;
+        let record = Record::Delivery {
+            at: chrono::Utc::now(),
+            idempotency_key: idempotency_key.to_string(),
+            entries,
+        };
+        self.append(&record).await?;
+        index.add(record);
+        Ok(())
+    }
+
+    /// Whether the entry with this key has already been dealt with.
+    pub async fn seen_entry(&self, entry: &str) -> bool {
+        self.index.lock().await.entries.contains(entry)
+    }
+
+    pub async fn record_entry(&self, record: Record) -> anyhow::Result<()> {
+        let mut index = self.index.lock().await;
+        self.append(&record).await?;
+        index.add(record);
+        Ok(())
+    }
+
+    async fn append(&self, record: &Record) -> anyhow::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut f = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Error opening {}", self.path.display()))?;
+        f.write_all(&line).await?;
+        f.flush().await?;
+        Ok(())
+    }
+}
+
+/// Drops a line left unfinished at the end of the log (e.g. by a crash
+/// mid-append), so the next record doesn't get appended onto it.
+fn truncate_torn_line(path: &std::path::Path) -> anyhow::Result<()> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Error reading {}", path.display())),
+    };
+    if contents.is_empty() || contents.ends_with(b"\n") {
+        return Ok(());
+    }
+    let complete = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    tracing::warn!(
+        "Dropping an unfinished line at the end of {}",
+        path.display()
+    );
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_len(complete as u64))
+        .with_context(|| format!("Error truncating {}", path.display()))
+}
+
+/// Every record in the log at `path`, oldest first. Lines that can't be
+/// parsed are skipped.
+pub fn read(path: &std::path::Path) -> anyhow::Result<Vec<Record>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Error reading {}", path.display())),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::warn!("Skipping bad line in {}: {e}", path.display());
+                None
+            }
+        })
+        .collect())
+}
+
+/// A plain-text listing of the most recent `limit` records, newest first.
+pub fn history(records: &[Record], limit: usize) -> anyhow::Result<String> {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    for record in records.iter().rev().take(limit) {
+        match record {
+            Record::Delivery {
+                at,
+                idempotency_key,
+                entries,
+            } => writeln!(
+                out,
+                "{}  delivery {idempotency_key} with {entries} entries",
+                at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+            ),
+            Record::Entry {
+                at,
+                entry_number,
+                description,
+                substantive,
+                category,
+                printed,
+                ..
+            } => {
+                let mut description = description.clone().unwrap_or_default();
+                if description.chars().count() > 60 {
+                    description = description.chars().take(57).collect::<String>() + "...";
+                }
+                writeln!(
+                    out,
+                    "{}    #{:<5} {:<8} {:<14} {description}",
+                    at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S"),
+                    entry_number.map(|n| n.to_string()).unwrap_or_default(),
+                    match (printed, substantive) {
+                        (true, _) => "printed",
+                        (false, true) => "failed",
+                        (false, false) => "skipped",
+                    },
+                    category,
+                )
+            }
+        }?;
+    }
+    Ok(out)
+}
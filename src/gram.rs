@@ -0,0 +1,68 @@
+use base64::Engine;
+
+const DESCRIPTION_PROMPT: &str = "Write a short description of what's depicted in the drawing. It should be at most a sentence. If the drawing prompts you with a question, you should try to answer!";
+
+pub struct Gram {
+    pub received_at: chrono::DateTime<chrono::Local>,
+    pub peer_ip: Option<String>,
+    pub user_name: Option<String>,
+    pub image: image::GrayImage,
+    pub description: Option<String>,
+}
+
+pub fn gram_receipt(gram: &Gram) -> crate::receipt::Receipt {
+    let mut receipt = crate::receipt::Receipt::new()
+        .title("Gram")
+        .centered(gram.received_at.format("%A %B %d, %Y").to_string())
+        .feed(2)
+        .field(
+            "Received At",
+            gram.received_at.format("%I:%M:%S %p").to_string(),
+        );
+    if let Some(peer_ip) = &gram.peer_ip {
+        receipt = receipt.field("Peer IP", peer_ip);
+    }
+    if let Some(user_name) = &gram.user_name {
+        receipt = receipt.field("User", user_name);
+    }
+
+    receipt = receipt.feed(2).image(gram.image.clone());
+
+    if let Some(description) = &gram.description {
+        receipt = receipt.feed(2).field("Description", description);
+    }
+
+    receipt.feed(5).cut()
+}
+
+/// Ask the model to describe (or answer) a drawing or photo.
+pub async fn describe(
+    llm: &dyn crate::llm::LlmProvider,
+    options: &crate::anthropic::CompletionOptions,
+    image: &[u8],
+    media_type: crate::anthropic::ImageMediaType,
+) -> anyhow::Result<String> {
+    let data = base64::prelude::BASE64_STANDARD.encode(image);
+    llm.complete(
+        options,
+        &crate::anthropic::Conversation::new().user([
+            crate::anthropic::MessageContent::Text {
+                text: DESCRIPTION_PROMPT,
+                cache_control: None,
+            },
+            crate::anthropic::MessageContent::Image {
+                source: crate::anthropic::ImageSource::new_base64(media_type, &data),
+                cache_control: None,
+            },
+        ]),
+    )
+    .await
+}
+
+pub fn startup_receipt(now: chrono::DateTime<chrono::Local>) -> crate::receipt::Receipt {
+    crate::receipt::Receipt::new()
+        .title("Gram Server Started")
+        .centered(now.format("%A %B %d, %Y at %I:%M:%S %p").to_string())
+        .feed(3)
+        .cut()
+}
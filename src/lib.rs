@@ -1,5 +1,16 @@
 pub mod anthropic;
+pub mod brief;
+pub mod config;
 pub mod courtlistener;
+pub mod delivery_log;
+pub mod gram;
+pub mod llm;
+pub mod mock_anthropic;
+pub mod preview;
 pub mod printer;
+pub mod queue;
+pub mod receipt;
+pub mod status;
 pub mod todoist;
+pub mod usage;
 pub mod weather;
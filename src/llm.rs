@@ -0,0 +1,333 @@
+//! Completions from more than one kind of LLM server, so the brief and grams
+//! keep working when Anthropic can't be reached.
+
+/// The name of the built-in Anthropic provider in `llm_routes`.
+pub const ANTHROPIC: &str = "anthropic";
+
+/// Whether `err` is only because the LLM budget has been used up.
+pub fn is_budget_exceeded(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref(),
+        Some(crate::anthropic::AnthropicError::BudgetExceeded(_))
+    )
+}
+
+/// Something that can write the next assistant turn of a conversation.
+pub trait LlmProvider: Send + Sync {
+    /// For logs.
+    fn name(&self) -> &str;
+
+    fn complete<'a>(
+        &'a self,
+        options: &'a crate::anthropic::CompletionOptions,
+        conversation: &'a crate::anthropic::Conversation<'a>,
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>>;
+
+    /// Like [LlmProvider::complete], but also hands the text to `on_text` a
+    /// piece at a time as it's generated. Providers that can't stream hand
+    /// it over all at once.
+    fn stream<'a>(
+        &'a self,
+        options: &'a crate::anthropic::CompletionOptions,
+        conversation: &'a crate::anthropic::Conversation<'a>,
+        on_text: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let text = self.complete(options, conversation).await?;
+            on_text(&text);
+            Ok(text)
+        })
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(tag = "type")]
+pub enum ProviderConfig {
+    /// A server with an OpenAI-style `/v1/chat/completions` endpoint, e.g.
+    /// llama.cpp or Ollama.
+    #[serde(rename = "openai")]
+    OpenAi {
+        base_url: String,
+        /// Used in place of the model in the call site's options.
+        model: String,
+        /// The environment variable holding the API key, if the server needs
+        /// one.
+        api_key_env: Option<String>,
+    },
+}
+
+/// Talks to an OpenAI-compatible chat completions endpoint. Thinking
+/// settings are ignored.
+pub struct OpenAiProvider {
+    name: String,
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    stop: &'a [String],
+}
+
+#[derive(serde::Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: Vec<ChatContent<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatContent<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ChatImageUrl },
+}
+
+#[derive(serde::Serialize)]
+struct ChatImageUrl {
+    url: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+}
+
+fn chat_content<'a>(
+    content: &'a crate::anthropic::MessageContent<'a>,
+) -> anyhow::Result<ChatContent<'a>> {
+    Ok(match content {
+        crate::anthropic::MessageContent::Text { text, .. } => ChatContent::Text { text },
+        crate::anthropic::MessageContent::Image { source, .. } => ChatContent::ImageUrl {
+            image_url: ChatImageUrl { url: source.url() },
+        },
+        // There's no widely supported way to send these.
+        crate::anthropic::MessageContent::Document { .. } => {
+            anyhow::bail!("OpenAI-compatible providers can't be sent documents")
+        }
+    })
+}
+
+impl OpenAiProvider {
+    pub fn new(name: &str, config: &ProviderConfig, http: reqwest::Client) -> anyhow::Result<Self> {
+        let ProviderConfig::OpenAi {
+            base_url,
+            model,
+            api_key_env,
+        } = config;
+        let api_key = api_key_env
+            .as_ref()
+            .map(|var| {
+                std::env::var(var)
+                    .map_err(|_| anyhow::anyhow!("Missing env var {var} for LLM provider {name:?}"))
+            })
+            .transpose()?;
+        Ok(OpenAiProvider {
+            name: name.to_string(),
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.clone(),
+            api_key,
+        })
+    }
+}
+
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete<'a>(
+        &'a self,
+        options: &'a crate::anthropic::CompletionOptions,
+        conversation: &'a crate::anthropic::Conversation<'a>,
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let mut messages = Vec::new();
+            if !conversation.system_blocks().is_empty() {
+                messages.push(ChatMessage {
+                    role: "system",
+                    content: conversation
+                        .system_blocks()
+                        .iter()
+                        .map(chat_content)
+                        .collect::<anyhow::Result<_>>()?,
+                });
+            }
+            for (role, content) in conversation.turns() {
+                messages.push(ChatMessage {
+                    role,
+                    content: content
+                        .iter()
+                        .map(chat_content)
+                        .collect::<anyhow::Result<_>>()?,
+                });
+            }
+            let request = ChatRequest {
+                model: &self.model,
+                messages,
+                max_tokens: options.max_tokens,
+                temperature: options.temperature,
+                stop: &options.stop_sequences,
+            };
+
+            let mut builder = self
+                .http
+                .post(format!("{}/v1/chat/completions", self.base_url))
+                .json(&request);
+            if let Some(api_key) = &self.api_key {
+                builder = builder.bearer_auth(api_key);
+            }
+            let response = builder.send().await?;
+            let status = response.status();
+            if !status.is_success() {
+                anyhow::bail!(
+                    "{} returned {status}: {}",
+                    self.name,
+                    response.text().await?
+                );
+            }
+            let response: ChatResponse = response.json().await?;
+            let choice = response.choices.into_iter().next();
+            match choice {
+                Some(ChatChoice {
+                    message:
+                        ChatResponseMessage {
+                            content: Some(content),
+                        },
+                    ..
+                }) if !content.is_empty() => Ok(content),
+                choice => anyhow::bail!(
+                    "No text content in response from {} (finish reason: {})",
+                    self.name,
+                    choice
+                        .and_then(|c| c.finish_reason)
+                        .as_deref()
+                        .unwrap_or("none")
+                ),
+            }
+        })
+    }
+}
+
+/// Tries each provider in turn until one succeeds.
+pub struct FallbackChain {
+    name: String,
+    providers: Vec<Box<dyn LlmProvider>>,
+}
+
+impl FallbackChain {
+    pub fn new(providers: Vec<Box<dyn LlmProvider>>) -> Self {
+        FallbackChain {
+            name: providers
+                .iter()
+                .map(|p| p.name())
+                .collect::<Vec<_>>()
+                .join(", "),
+            providers,
+        }
+    }
+
+    /// The providers a route lists, in order. [ANTHROPIC] is `anthropic`,
+    /// and is left out if there's no API key for it; the rest are
+    /// configured in `providers`. `None` if none of them are available.
+    pub fn for_route(
+        route: &[String],
+        anthropic: Option<&crate::anthropic::Client>,
+        providers: &std::collections::BTreeMap<String, ProviderConfig>,
+        http: &reqwest::Client,
+    ) -> anyhow::Result<Option<Self>> {
+        let mut chain: Vec<Box<dyn LlmProvider>> = Vec::new();
+        for name in route {
+            if name == ANTHROPIC {
+                if let Some(anthropic) = anthropic {
+                    chain.push(Box::new(anthropic.clone()));
+                }
+                continue;
+            }
+            let config = providers
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown LLM provider {name:?}"))?;
+            chain.push(Box::new(OpenAiProvider::new(name, config, http.clone())?));
+        }
+        Ok((!chain.is_empty()).then(|| FallbackChain::new(chain)))
+    }
+}
+
+impl LlmProvider for FallbackChain {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn complete<'a>(
+        &'a self,
+        options: &'a crate::anthropic::CompletionOptions,
+        conversation: &'a crate::anthropic::Conversation<'a>,
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        self.stream(options, conversation, &|_| {})
+    }
+
+    fn stream<'a>(
+        &'a self,
+        options: &'a crate::anthropic::CompletionOptions,
+        conversation: &'a crate::anthropic::Conversation<'a>,
+        on_text: &'a (dyn Fn(&str) + Send + Sync),
+    ) -> crate::printer::BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let mut last_err = None;
+            // Kept over later errors, so callers can still tell the budget
+            // is why nothing was generated.
+            let mut budget_err = None;
+            for provider in &self.providers {
+                let streamed = std::sync::atomic::AtomicBool::new(false);
+                let on_provider_text = |text: &str| {
+                    streamed.store(true, std::sync::atomic::Ordering::Relaxed);
+                    on_text(text);
+                };
+                match provider
+                    .stream(options, conversation, &on_provider_text)
+                    .await
+                {
+                    Ok(text) => return Ok(text),
+                    // Some of its text has already been used, so the next
+                    // provider can't take over.
+                    Err(err) if streamed.load(std::sync::atomic::Ordering::Relaxed) => {
+                        return Err(
+                            err.context(format!("{} failed partway through", provider.name()))
+                        );
+                    }
+                    Err(err) => {
+                        tracing::warn!(provider = provider.name(), "LLM provider failed: {err:#}");
+                        let err = err.context(format!("{} failed", provider.name()));
+                        if is_budget_exceeded(&err) {
+                            budget_err.get_or_insert(err);
+                        } else {
+                            last_err = Some(err);
+                        }
+                    }
+                }
+            }
+            Err(budget_err
+                .or(last_err)
+                .unwrap_or_else(|| anyhow::anyhow!("No LLM providers configured")))
+        })
+    }
+}
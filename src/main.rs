@@ -1,42 +1,110 @@
 use anyhow::Context;
-use base64::Engine;
+use axum::response::IntoResponse;
 use clap::Parser;
-use epson::AsyncWriterExt;
 use image::buffer::ConvertBuffer;
 
 static TODOIST_API_TOKEN: std::sync::LazyLock<String> =
     std::sync::LazyLock::new(|| std::env::var("TODOIST_API_TOKEN").expect("Missing env var"));
 static ANTHROPIC_API_TOKEN: std::sync::LazyLock<Option<String>> =
     std::sync::LazyLock::new(|| std::env::var("ANTHROPIC_API_TOKEN").ok());
+static ANTHROPIC_BASE_URL: std::sync::LazyLock<Option<String>> =
+    std::sync::LazyLock::new(|| std::env::var("ANTHROPIC_BASE_URL").ok());
 static COURTLISTENER_WEBHOOK_SECRET: std::sync::LazyLock<Option<String>> =
     std::sync::LazyLock::new(|| std::env::var("COURTLISTENER_WEBHOOK_SECRET").ok());
 
 #[derive(clap::Parser)]
 struct Cli {
+    /// Path to a TOML config file
+    #[arg(long, global = true, env = "ADB_CONFIG")]
+    config: Option<std::path::PathBuf>,
+    /// Write raw ESC/POS output to this file instead of the printer ("-" for stdout)
+    #[arg(long, global = true, env = "ADB_OUTPUT")]
+    output: Option<std::path::PathBuf>,
+    #[command(flatten)]
+    printer: PrinterArgs,
     #[command(subcommand)]
     command: Commands,
 }
 
+/// Overrides for the `[printer]` section of the config. These have no
+/// effect when named `[printers.<name>]` are configured.
+#[derive(clap::Args)]
+struct PrinterArgs {
+    /// Printer hostname or IP address
+    #[arg(long = "printer-host", global = true, env = "ADB_PRINTER_HOST")]
+    host: Option<String>,
+    /// Printer TCP port
+    #[arg(long = "printer-port", global = true, env = "ADB_PRINTER_PORT")]
+    port: Option<u16>,
+    /// Printer model
+    #[arg(long = "printer-model", global = true, env = "ADB_PRINTER_MODEL")]
+    model: Option<adb::printer::Model>,
+    /// Printer paper width
+    #[arg(
+        long = "printer-paper-width",
+        global = true,
+        env = "ADB_PRINTER_PAPER_WIDTH"
+    )]
+    paper_width: Option<adb::printer::PaperWidth>,
+    /// Print speed (1-9)
+    #[arg(long = "printer-speed", global = true, env = "ADB_PRINTER_SPEED")]
+    speed: Option<u8>,
+    /// Whether to switch the printer into unicode mode
+    #[arg(long = "printer-unicode", global = true, env = "ADB_PRINTER_UNICODE")]
+    unicode: Option<bool>,
+}
+
+impl PrinterArgs {
+    fn apply(self, config: &mut adb::printer::PrinterConfig) {
+        if let Some(host) = self.host {
+            config.host = host;
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(model) = self.model {
+            config.model = model;
+        }
+        if let Some(paper_width) = self.paper_width {
+            config.paper_width = paper_width;
+        }
+        if let Some(speed) = self.speed {
+            config.speed = speed;
+        }
+        if let Some(unicode) = self.unicode {
+            config.unicode = Some(unicode);
+        }
+    }
+}
+
 #[derive(clap::Subcommand)]
 enum Commands {
-    Adb,
+    Adb {
+        /// Render the brief to a PNG file instead of printing it
+        #[arg(long, value_name = "PATH")]
+        preview: Option<std::path::PathBuf>,
+    },
     Gram,
+    /// Print LLM token usage and spend from the usage ledger
+    Usage {
+        /// How many of the most recent days to list
+        #[arg(long, default_value_t = 30)]
+        days: usize,
+    },
+    Court {
+        #[command(subcommand)]
+        command: CourtCommand,
+    },
 }
 
-async fn print_gram_startup_message() -> anyhow::Result<()> {
-    let mut w = adb::printer::new_epson_writer().await?;
-    let now = chrono::offset::Local::now();
-
-    w.justify(epson::Alignment::Center).await?;
-    w.underline(true).await?;
-    w.write_all(b"Gram Server Started\n").await?;
-    w.underline(false).await?;
-    w.write_all(format!("{}\n", now.format("%A %B %d, %Y at %I:%M:%S %p")).as_bytes())
-        .await?;
-    w.feed(3).await?;
-    w.cut().await?;
-
-    Ok(())
+#[derive(clap::Subcommand)]
+enum CourtCommand {
+    /// List recent CourtListener deliveries and what happened to each entry
+    History {
+        /// How many of the most recent records to list
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
 }
 
 #[tokio::main]
@@ -45,14 +113,90 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
 
+    let mut config = match &cli.config {
+        Some(path) => adb::config::Config::load(path)?,
+        None => adb::config::Config::default(),
+    };
+    cli.printer.apply(&mut config.printer);
+
+    let printers: std::collections::BTreeMap<String, adb::printer::Printer> = config
+        .printers()
+        .into_iter()
+        .map(|(name, printer_config)| {
+            let sink = sink_for(&cli.command, cli.output.as_deref(), &printer_config);
+            (name, adb::printer::Printer::new(printer_config, sink))
+        })
+        .collect();
+
+    let http = reqwest::Client::new();
+    let ledger = std::sync::Arc::new(adb::usage::Ledger::new(config.usage.clone()));
+    let anthropic = ANTHROPIC_API_TOKEN.as_deref().map(|token| {
+        let client = adb::anthropic::Client::new(http.clone(), token).ledger(ledger.clone());
+        match ANTHROPIC_BASE_URL.as_deref() {
+            Some(base_url) => client.base_url(base_url),
+            None => client,
+        }
+    });
+
     match cli.command {
-        Commands::Adb => adb().await,
-        Commands::Gram => gram().await,
+        Commands::Adb { .. } => {
+            let brief_printers = config
+                .route(adb::config::JobKind::Brief)?
+                .iter()
+                .map(|name| printers[name].clone())
+                .collect::<Vec<_>>();
+            let llm = adb::llm::FallbackChain::for_route(
+                &config.llm_route(adb::config::JobKind::Brief)?,
+                anthropic
+                    .map(|client| client.call_site(adb::usage::CallSite::Brief))
+                    .as_ref(),
+                &config.llm_providers,
+                &http,
+            )?
+            .context("No LLM provider is available for the US history fact")?;
+            adb(&brief_printers, &llm, &config.completions.history_fact).await
+        }
+        Commands::Gram => gram(&config, printers, http, anthropic).await,
+        Commands::Usage { days } => {
+            print!("{}", ledger.report(days).await?);
+            Ok(())
+        }
+        Commands::Court {
+            command: CourtCommand::History { limit },
+        } => {
+            let records = adb::delivery_log::read(&config.courtlistener.delivery_log)?;
+            print!("{}", adb::delivery_log::history(&records, limit)?);
+            Ok(())
+        }
     }
 }
 
-async fn adb() -> anyhow::Result<()> {
-    let mut w = adb::printer::new_epson_writer().await?;
+fn sink_for(
+    command: &Commands,
+    output: Option<&std::path::Path>,
+    printer_config: &adb::printer::PrinterConfig,
+) -> std::sync::Arc<dyn adb::printer::PrintSink> {
+    match (command, output) {
+        (
+            Commands::Adb {
+                preview: Some(path),
+            },
+            _,
+        ) => std::sync::Arc::new(adb::preview::PreviewSink::new(
+            path,
+            printer_config.paper_width.dots(),
+        )),
+        (_, Some(path)) if path.as_os_str() == "-" => std::sync::Arc::new(adb::printer::StdoutSink),
+        (_, Some(path)) => std::sync::Arc::new(adb::printer::FileSink::new(path)),
+        (_, None) => std::sync::Arc::new(adb::printer::TcpSink::new(printer_config)),
+    }
+}
+
+async fn adb(
+    printers: &[adb::printer::Printer],
+    llm: &dyn adb::llm::LlmProvider,
+    history_fact_options: &adb::anthropic::CompletionOptions,
+) -> anyhow::Result<()> {
     let today = chrono::offset::Local::now();
 
     let client = reqwest::Client::new();
@@ -71,69 +215,42 @@ async fn adb() -> anyhow::Result<()> {
             .await
             .context("Error encountered getting TODO items")
     };
-    let us_history_fact_fut = async {
-        adb::anthropic::get_completion(
-            &client,
-            ANTHROPIC_API_TOKEN
-                .as_ref()
-                .expect("Anthropic API token not present"),
-            [adb::anthropic::MessageContent::Text {
+    // The fact is printed as it streams in, after the rest of the brief.
+    let (fact_tx, fact_rx) = tokio::sync::mpsc::unbounded_channel();
+    let us_history_fact_fut = async move {
+        let conversation =
+            adb::anthropic::Conversation::new().user([adb::anthropic::MessageContent::Text {
                 text: &us_history_prompt,
-            }],
-        )
-        .await
-        .context("Error encountered getting US history fact")
-    };
-    let (weather, todo_items, us_history_fact) =
-        tokio::try_join!(weather_fut, todo_fut, us_history_fact_fut,)?;
-
-    w.justify(epson::Alignment::Center).await?;
-    w.underline(true).await?;
-    w.write_all(b"Alex's Daily Brief\n").await?;
-    w.underline(false).await?;
-    w.justify(epson::Alignment::Left).await?;
-
-    w.justify(epson::Alignment::Center).await?;
-    w.write_all(format!("{}\n", today.format("%A %B %d, %Y")).as_bytes())
-        .await?;
-    w.justify(epson::Alignment::Left).await?;
-
-    w.feed(2).await?;
-    w.underline(true).await?;
-    w.write_all(b"Weather Forecast:\n").await?;
-    w.underline(false).await?;
-    for (day, forecast) in weather {
-        w.write_all(format!("{}: {}\n", day, forecast).as_bytes())
-            .await?;
-    }
-
-    w.feed(2).await?;
-    w.underline(true).await?;
-    w.write_all(b"TODO:\n").await?;
-    w.underline(false).await?;
-
-    for todo in todo_items {
-        w.write_all(b"[ ] ").await?;
-        if let Some(time) = todo.time {
-            w.emphasize(true).await?;
-            w.write_all(format!("{}", time.format("%-I:%M %p")).as_bytes())
-                .await?;
-            w.emphasize(false).await?;
-            w.write_all(b" ").await?;
+                cache_control: None,
+            }]);
+        let on_text = |delta: &str| {
+            let _ = fact_tx.send(delta.to_string());
+        };
+        match llm
+            .stream(history_fact_options, &conversation, &on_text)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) if adb::llm::is_budget_exceeded(&err) => {
+                tracing::warn!("Leaving out the US history fact: {err:#}");
+                Ok(())
+            }
+            Err(err) => Err(err.context("Error encountered getting US history fact")),
         }
-        w.write_all(format!("{}\n", todo.content).as_bytes()).await?;
-    }
-
-    w.feed(2).await?;
-    w.underline(true).await?;
-    w.write_all(b"US History Fact:\n").await?;
-    w.underline(false).await?;
-    w.write_all(us_history_fact.as_bytes()).await?;
-
-    w.feed(5).await?;
-    w.cut().await?;
-
-    Ok(())
+    };
+    let print_fut = async {
+        let (weather, todo_items) = tokio::try_join!(weather_fut, todo_fut)?;
+        let brief = adb::brief::Brief {
+            date: today,
+            weather,
+            todo_items,
+            us_history_fact: None,
+        };
+        adb::brief::print_streaming(printers, &brief, fact_rx).await
+    };
+    let (fact_result, print_result) = tokio::join!(us_history_fact_fut, print_fut);
+    print_result?;
+    fact_result
 }
 
 const DRAWING_HTML: &str = include_str!("drawing.html");
@@ -161,19 +278,55 @@ where
     }
 }
 
-const WIDTH: u16 = 576;
-
 #[derive(serde::Deserialize)]
 struct PostGramOptions {
     description: Option<bool>,
     rotate_if_landscape: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    preview: bool,
+    /// Print on this printer instead of the ones the gram route lists.
+    printer: Option<String>,
+}
+
+/// Accepts `1`/`0` in addition to `true`/`false`, for `?preview=1`.
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = <std::borrow::Cow<'de, str> as serde::Deserialize>::deserialize(deserializer)?;
+    match value.as_ref() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(other),
+            &"a boolean",
+        )),
+    }
+}
+
+struct AppState {
+    queues: std::collections::BTreeMap<String, std::sync::Arc<adb::queue::PrintQueue>>,
+    gram_printers: Vec<String>,
+    court_printers: Vec<String>,
+    completions: adb::config::Completions,
+    anthropic: Option<adb::anthropic::Client>,
+    gram_llm: Option<adb::llm::FallbackChain>,
+    webhook_guard: adb::courtlistener::WebhookGuard,
+    delivery_log: std::sync::Arc<adb::delivery_log::DeliveryLog>,
+}
+
+impl AppState {
+    fn queues(&self, names: &[String]) -> Vec<std::sync::Arc<adb::queue::PrintQueue>> {
+        names.iter().map(|name| self.queues[name].clone()).collect()
+    }
 }
 
 async fn post_gram(
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<AppState>>,
     headers: axum::http::header::HeaderMap,
     axum::extract::Query(opts): axum::extract::Query<PostGramOptions>,
     mut form: axum::extract::Multipart,
-) -> Result<axum::http::StatusCode, AppError> {
+) -> Result<axum::response::Response, AppError> {
     let now = chrono::offset::Local::now();
 
     let mut image_post_data = None;
@@ -184,9 +337,46 @@ async fn post_gram(
         image_post_data = Some(field.bytes().await?);
     }
     let Some(image_post_data) = image_post_data else {
-        return Ok(axum::http::StatusCode::BAD_REQUEST);
+        return Ok(axum::http::StatusCode::BAD_REQUEST.into_response());
+    };
+
+    let targets = match opts.printer {
+        Some(name) if state.queues.contains_key(&name) => state.queues(&[name]),
+        Some(name) => {
+            return Ok((
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("Unknown printer {name:?}"),
+            )
+                .into_response());
+        }
+        None => state.queues(&state.gram_printers),
+    };
+    if targets.is_empty() {
+        return Ok((
+            axum::http::StatusCode::CONFLICT,
+            "No printers are routed for grams",
+        )
+            .into_response());
+    }
+
+    // Anything the API can describe: PNG from the drawing page, or a photo.
+    let Some((format, media_type)) =
+        image::guess_format(&image_post_data)
+            .ok()
+            .and_then(|format| {
+                Some((
+                    format,
+                    adb::anthropic::ImageMediaType::from_format(format).ok()?,
+                ))
+            })
+    else {
+        return Ok((
+            axum::http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Images must be PNG, JPEG, GIF or WebP",
+        )
+            .into_response());
     };
-    let img = image::load_from_memory_with_format(&image_post_data, image::ImageFormat::Png)?;
+    let img = image::load_from_memory_with_format(&image_post_data, format)?;
 
     let img = if img.width() > img.height() && opts.rotate_if_landscape.unwrap_or(false) {
         img.rotate90()
@@ -194,104 +384,199 @@ async fn post_gram(
         img
     };
 
-    let img = img.resize(
-        WIDTH.into(),
-        4096 * 512,
-        image::imageops::FilterType::Lanczos3,
-    );
-    let img = image::imageops::colorops::brighten(&img, 64);
-    let mut img: image::GrayImage = img.convert();
-    image::imageops::colorops::dither(&mut img, &image::imageops::colorops::BiLevel);
-
     let description = if matches!(opts.description, None | Some(true))
-        && let Some(anthropic_api_token) = ANTHROPIC_API_TOKEN.as_ref()
+        && let Some(llm) = &state.gram_llm
     {
-        let client = reqwest::Client::new();
-        Some(adb::anthropic::get_completion(
-            &client,
-            &anthropic_api_token,
-            [
-                adb::anthropic::MessageContent::Text {
-                    text: "Write a short description of what's depicted in the drawing. It should be at most a sentence. If the drawing prompts you with a question, you should try to answer!",
-                },
-                adb::anthropic::MessageContent::Image {
-                    source: adb::anthropic::ImageSource::new_base64("image/png", &base64::prelude::BASE64_STANDARD.encode(&image_post_data))
-                },
-            ],
+        let result = adb::gram::describe(
+            llm,
+            &state.completions.gram_description,
+            &image_post_data,
+            media_type,
         )
-        .await?)
+        .await;
+        match result {
+            Ok(description) => Some(description),
+            Err(err) if adb::llm::is_budget_exceeded(&err) => {
+                tracing::warn!("Printing gram without a description: {err:#}");
+                None
+            }
+            Err(err) => return Err(err.into()),
+        }
     } else {
         None
     };
 
-    let mut w = adb::printer::new_epson_writer().await?;
-    w.justify(epson::Alignment::Center).await?;
-    w.underline(true).await?;
-    w.write_all(b"Gram\n").await?;
-    w.underline(false).await?;
-    w.justify(epson::Alignment::Left).await?;
-
-    w.justify(epson::Alignment::Center).await?;
-    w.write_all(format!("{}\n", now.format("%A %B %d, %Y")).as_bytes())
-        .await?;
-    w.justify(epson::Alignment::Left).await?;
-
-    w.feed(2).await?;
-    w.underline(true).await?;
-    w.write_all(b"Received At:").await?;
-    w.underline(false).await?;
-    w.write_all(format!(" {}\n", now.format("%I:%M:%S %p")).as_bytes())
-        .await?;
-
-    if let Some(peer_ip) = headers.get("X-Forwarded-For") {
-        let peer_ip = peer_ip.to_str()?;
-        w.underline(true).await?;
-        w.write_all(b"Peer IP:").await?;
-        w.underline(false).await?;
-        w.write_all(format!(" {peer_ip}\n").as_bytes()).await?;
-    }
-    if let Some(user_name) = headers.get("X-Gram-User") {
-        let user_name = user_name.to_str()?;
-        w.underline(true).await?;
-        w.write_all(b"User:").await?;
-        w.underline(false).await?;
-        w.write_all(format!(" {user_name}\n").as_bytes()).await?;
-    }
+    let peer_ip = headers
+        .get("X-Forwarded-For")
+        .map(|v| v.to_str())
+        .transpose()?;
+    let user_name = headers.get("X-Gram-User").map(|v| v.to_str()).transpose()?;
 
-    w.feed(2).await?;
-    w.print_image(img).await?;
+    let img = image::imageops::colorops::brighten(&img, 64);
+    let receipt = adb::gram::gram_receipt(&adb::gram::Gram {
+        received_at: now,
+        peer_ip: peer_ip.map(str::to_string),
+        user_name: user_name.map(str::to_string),
+        image: img.convert(),
+        description,
+    });
+
+    if opts.preview {
+        let printer = targets[0].printer();
+        let preview = adb::preview::render(
+            &printer.render(&receipt).await?,
+            printer.config().paper_width.dots(),
+        )?;
+        let mut png = std::io::Cursor::new(Vec::new());
+        preview.write_to(&mut png, image::ImageFormat::Png)?;
+        return Ok((
+            [(axum::http::header::CONTENT_TYPE, "image/png")],
+            png.into_inner(),
+        )
+            .into_response());
+    }
 
-    if let Some(description) = description {
-        w.feed(2).await?;
-        w.underline(true).await?;
-        w.write_all(b"Description:").await?;
-        w.underline(false).await?;
-        w.write_all(format!(" {description}\n").as_bytes()).await?;
+    let mut ids = Vec::new();
+    for queue in &targets {
+        let id = queue
+            .submit_receipt(&receipt, adb::queue::Priority::Normal)
+            .await?;
+        ids.push(id);
     }
+    let jobs: Vec<_> = targets
+        .iter()
+        .zip(&ids)
+        .map(|(queue, id)| serde_json::json!({ "printer": queue.name(), "id": id }))
+        .collect();
+
+    Ok((
+        axum::http::StatusCode::CREATED,
+        [(axum::http::header::LOCATION, format!("/jobs/{}", ids[0]))],
+        axum::Json(serde_json::json!({ "id": ids[0], "jobs": jobs })),
+    )
+        .into_response())
+}
 
-    w.feed(5).await?;
-    w.cut().await?;
+async fn get_job(
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<axum::response::Response, AppError> {
+    for queue in state.queues.values() {
+        if let Some(status) = queue.status(&id).await? {
+            return Ok(axum::Json(status).into_response());
+        }
+    }
+    Ok(axum::http::StatusCode::NOT_FOUND.into_response())
+}
 
-    Ok(axum::http::StatusCode::CREATED)
+/// The latest status report for every printer, by name. A printer that
+/// hasn't been checked yet maps to `null`.
+async fn get_printer_status(
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<AppState>>,
+) -> axum::Json<std::collections::BTreeMap<String, Option<adb::status::StatusReport>>> {
+    axum::Json(
+        state
+            .queues
+            .iter()
+            .map(|(name, queue)| (name.clone(), queue.printer_status()))
+            .collect(),
+    )
 }
 
 async fn post_courtlistener_webhook(
-    axum::Json(webhook): axum::Json<adb::courtlistener::CourtListenerWebhook>,
-) -> Result<axum::http::StatusCode, AppError> {
-    let client = reqwest::Client::new();
-    adb::courtlistener::handle_webhook(
-        &client,
-        ANTHROPIC_API_TOKEN
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<AppState>>,
+    axum::extract::ConnectInfo(peer): axum::extract::ConnectInfo<std::net::SocketAddr>,
+    headers: axum::http::header::HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<axum::response::Response, AppError> {
+    let header = |name| headers.get(name).map(|v| v.to_str().unwrap_or_default());
+    let delivery = state.webhook_guard.check(
+        peer.ip(),
+        header("X-Forwarded-For"),
+        header("Idempotency-Key"),
+        &body,
+    );
+    let delivery = match delivery {
+        Ok(delivery) => delivery,
+        Err(rejection) => {
+            tracing::warn!("Rejected CourtListener webhook: {rejection}");
+            let status = match rejection {
+                adb::courtlistener::WebhookRejection::ForbiddenSource(_) => {
+                    axum::http::StatusCode::FORBIDDEN
+                }
+                _ => axum::http::StatusCode::BAD_REQUEST,
+            };
+            return Ok((status, rejection.to_string()).into_response());
+        }
+    };
+
+    let new = adb::courtlistener::handle_webhook(
+        state
+            .anthropic
             .as_ref()
-            .expect("Anthropic API token not present"),
-        webhook,
+            .map(|client| client.call_site(adb::usage::CallSite::Court))
+            .as_ref(),
+        state.completions.docket_classifier.clone(),
+        state.queues(&state.court_printers),
+        state.delivery_log.clone(),
+        delivery,
     )
     .await?;
-    Ok(axum::http::StatusCode::OK)
+    if !new {
+        // Already accepted; say so again so CourtListener stops retrying.
+        tracing::info!(
+            key = header("Idempotency-Key"),
+            "Ignoring repeat CourtListener webhook delivery"
+        );
+    }
+    Ok(axum::http::StatusCode::OK.into_response())
 }
 
-async fn gram() -> anyhow::Result<()> {
-    print_gram_startup_message().await?;
+async fn gram(
+    config: &adb::config::Config,
+    printers: std::collections::BTreeMap<String, adb::printer::Printer>,
+    http: reqwest::Client,
+    anthropic: Option<adb::anthropic::Client>,
+) -> anyhow::Result<()> {
+    let gram_printers = config.route(adb::config::JobKind::Gram)?;
+    let court_printers = config.route(adb::config::JobKind::Court)?;
+    let gram_llm = adb::llm::FallbackChain::for_route(
+        &config.llm_route(adb::config::JobKind::Gram)?,
+        anthropic
+            .as_ref()
+            .map(|client| client.call_site(adb::usage::CallSite::Gram))
+            .as_ref(),
+        &config.llm_providers,
+        &http,
+    )?;
+
+    let mut queues = std::collections::BTreeMap::new();
+    for (name, printer) in printers {
+        let (queue, actor) =
+            adb::queue::PrintQueue::open(&name, config.queue_dir.join(&name), printer)?;
+        tokio::spawn(actor.run());
+        queues.insert(name, queue);
+    }
+
+    let state = std::sync::Arc::new(AppState {
+        queues,
+        gram_printers,
+        court_printers,
+        completions: config.completions.clone(),
+        anthropic,
+        gram_llm,
+        webhook_guard: adb::courtlistener::WebhookGuard::new(config.courtlistener.clone()),
+        delivery_log: std::sync::Arc::new(adb::delivery_log::DeliveryLog::open(
+            &config.courtlistener.delivery_log,
+        )?),
+    });
+
+    let startup = adb::gram::startup_receipt(chrono::offset::Local::now());
+    for queue in state.queues(&state.gram_printers) {
+        queue
+            .submit_receipt(&startup, adb::queue::Priority::Low)
+            .await?;
+    }
 
     let mut app = axum::Router::new()
         .route(
@@ -302,7 +587,9 @@ async fn gram() -> anyhow::Result<()> {
             "/photo/",
             axum::routing::get(|| async { axum::response::Html(PHOTO_HTML) }),
         )
-        .route("/gram/", axum::routing::post(post_gram));
+        .route("/gram/", axum::routing::post(post_gram))
+        .route("/jobs/{id}", axum::routing::get(get_job))
+        .route("/printer/status", axum::routing::get(get_printer_status));
 
     // Only register the CourtListener webhook route if the secret is configured
     if let Some(secret) = COURTLISTENER_WEBHOOK_SECRET.as_ref() {
@@ -313,12 +600,18 @@ async fn gram() -> anyhow::Result<()> {
         );
     }
 
-    let app = app.layer(axum::extract::DefaultBodyLimit::max(50 * 1024 * 1024));
+    let app = app
+        .layer(axum::extract::DefaultBodyLimit::max(50 * 1024 * 1024))
+        .with_state(state);
 
     let addr = "0.0.0.0:3000";
     let listener = tokio::net::TcpListener::bind(addr).await?;
     println!("Listening on {addr}");
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
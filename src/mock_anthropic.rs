@@ -0,0 +1,312 @@
+//! A stand-in for the Messages API, for testing without a network or key.
+//!
+//! Responses are scripted ahead of time and served in order, streamed when
+//! the request asks for it. Every request body is kept so tests can check
+//! what was sent. The same script also answers OpenAI-style chat
+//! completions, for testing [crate::llm::OpenAiProvider].
+
+use axum::response::IntoResponse;
+
+/// Token counts reported for every scripted message.
+pub const USAGE: crate::usage::Usage = crate::usage::Usage {
+    input_tokens: 100,
+    output_tokens: 20,
+    cache_creation_input_tokens: 0,
+    cache_read_input_tokens: 0,
+};
+
+#[derive(Clone, Debug)]
+pub enum MockResponse {
+    /// A message with this text.
+    Text(String),
+    /// A message that calls the tool `name` with `input`.
+    ToolUse {
+        name: String,
+        input: serde_json::Value,
+    },
+    /// A message the model declined to write.
+    Refusal,
+    /// An error response. Rate limit and overload errors say to retry
+    /// immediately, so tests don't wait on backoff.
+    Error {
+        status: u16,
+        error_type: String,
+        message: String,
+    },
+}
+
+impl MockResponse {
+    pub fn text(text: impl Into<String>) -> Self {
+        MockResponse::Text(text.into())
+    }
+
+    pub fn tool_use(name: impl Into<String>, input: serde_json::Value) -> Self {
+        MockResponse::ToolUse {
+            name: name.into(),
+            input,
+        }
+    }
+
+    pub fn error(status: u16, error_type: impl Into<String>, message: impl Into<String>) -> Self {
+        MockResponse::Error {
+            status,
+            error_type: error_type.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    responses: std::collections::VecDeque<MockResponse>,
+    requests: Vec<serde_json::Value>,
+}
+
+pub struct MockServer {
+    addr: std::net::SocketAddr,
+    state: std::sync::Arc<std::sync::Mutex<State>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Listen on a free port on localhost until dropped.
+    pub async fn start() -> anyhow::Result<Self> {
+        let state = std::sync::Arc::<std::sync::Mutex<State>>::default();
+        let app = axum::Router::new()
+            .route("/v1/messages", axum::routing::post(messages))
+            .route(
+                "/v1/chat/completions",
+                axum::routing::post(chat_completions),
+            )
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, app).await {
+                tracing::error!(error = ?err, "Mock Anthropic server failed");
+            }
+        });
+        Ok(MockServer { addr, state, task })
+    }
+
+    /// What to pass to [crate::anthropic::Client::base_url].
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client for this server.
+    pub fn client(&self) -> crate::anthropic::Client {
+        crate::anthropic::Client::new(reqwest::Client::new(), "mock-api-key")
+            .base_url(self.base_url())
+    }
+
+    /// Queue up the response to the next request.
+    pub fn push(&self, response: MockResponse) {
+        self.state.lock().unwrap().responses.push_back(response);
+    }
+
+    /// The bodies of every request so far, oldest first.
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn messages(
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<std::sync::Mutex<State>>>,
+    headers: axum::http::HeaderMap,
+    axum::Json(body): axum::Json<serde_json::Value>,
+) -> axum::response::Response {
+    let stream = body["stream"].as_bool().unwrap_or(false);
+    let model = body["model"].as_str().unwrap_or_default().to_string();
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(body);
+        state.responses.pop_front()
+    };
+
+    let response = if !headers.contains_key("x-api-key") {
+        MockResponse::error(401, "authentication_error", "x-api-key header is required")
+    } else if !headers.contains_key("anthropic-version") {
+        MockResponse::error(
+            400,
+            "invalid_request_error",
+            "anthropic-version header is required",
+        )
+    } else {
+        response
+            .unwrap_or_else(|| MockResponse::error(500, "api_error", "No scripted response left"))
+    };
+
+    let (content, stop_reason) = match response {
+        MockResponse::Text(text) => (
+            serde_json::json!([{ "type": "text", "text": text }]),
+            "end_turn",
+        ),
+        MockResponse::ToolUse { name, input } => (
+            serde_json::json!([{
+                "type": "tool_use",
+                "id": "toolu_mock",
+                "name": name,
+                "input": input,
+            }]),
+            "tool_use",
+        ),
+        MockResponse::Refusal => (serde_json::json!([]), "refusal"),
+        MockResponse::Error {
+            status,
+            error_type,
+            message,
+        } => return error_response(status, &error_type, &message),
+    };
+
+    if stream {
+        return event_stream(&model, &content, stop_reason);
+    }
+    axum::Json(serde_json::json!({
+        "id": "msg_mock",
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop_reason,
+        "usage": USAGE,
+    }))
+    .into_response()
+}
+
+async fn chat_completions(
+    axum::extract::State(state): axum::extract::State<std::sync::Arc<std::sync::Mutex<State>>>,
+    axum::Json(body): axum::Json<serde_json::Value>,
+) -> axum::response::Response {
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(body);
+        state.responses.pop_front()
+    };
+    let (content, finish_reason) = match response {
+        Some(MockResponse::Text(text)) => (Some(text), "stop"),
+        Some(MockResponse::Refusal) => (None, "content_filter"),
+        Some(MockResponse::ToolUse { .. }) => {
+            return error_response(400, "invalid_request_error", "Tools aren't supported");
+        }
+        Some(MockResponse::Error {
+            status,
+            error_type,
+            message,
+        }) => return error_response(status, &error_type, &message),
+        None => return error_response(500, "api_error", "No scripted response left"),
+    };
+    axum::Json(serde_json::json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": finish_reason,
+        }],
+    }))
+    .into_response()
+}
+
+fn error_response(status: u16, error_type: &str, message: &str) -> axum::response::Response {
+    let status = axum::http::StatusCode::from_u16(status)
+        .unwrap_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    let body = axum::Json(serde_json::json!({
+        "type": "error",
+        "error": { "type": error_type, "message": message },
+    }));
+    if status.as_u16() == 429 || status.as_u16() == 529 {
+        (status, [(axum::http::header::RETRY_AFTER, "0")], body).into_response()
+    } else {
+        (status, body).into_response()
+    }
+}
+
+/// The events the API streams for a message with `content`.
+fn event_stream(
+    model: &str,
+    content: &serde_json::Value,
+    stop_reason: &str,
+) -> axum::response::Response {
+    let mut events = vec![serde_json::json!({
+        "type": "message_start",
+        "message": {
+            "id": "msg_mock",
+            "type": "message",
+            "role": "assistant",
+            "model": model,
+            "content": [],
+            "stop_reason": null,
+            "usage": crate::usage::Usage {
+                output_tokens: 1,
+                ..USAGE
+            },
+        },
+    })];
+    for (index, block) in content.as_array().into_iter().flatten().enumerate() {
+        let (start, deltas) = match block["type"].as_str() {
+            Some("tool_use") => (
+                serde_json::json!({
+                    "type": "tool_use",
+                    "id": "toolu_mock",
+                    "name": block["name"],
+                    "input": {},
+                }),
+                vec![serde_json::json!({
+                    "type": "input_json_delta",
+                    "partial_json": block["input"].to_string(),
+                })],
+            ),
+            // A word at a time, like the real API sends text.
+            _ => (
+                serde_json::json!({ "type": "text", "text": "" }),
+                block["text"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .split_inclusive(' ')
+                    .map(|word| serde_json::json!({ "type": "text_delta", "text": word }))
+                    .collect(),
+            ),
+        };
+        events.push(serde_json::json!({
+            "type": "content_block_start",
+            "index": index,
+            "content_block": start,
+        }));
+        for delta in deltas {
+            events.push(serde_json::json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": delta,
+            }));
+        }
+        events.push(serde_json::json!({ "type": "content_block_stop", "index": index }));
+    }
+    events.push(serde_json::json!({
+        "type": "message_delta",
+        "delta": { "stop_reason": stop_reason, "stop_sequence": null },
+        "usage": { "output_tokens": USAGE.output_tokens },
+    }));
+    events.push(serde_json::json!({ "type": "message_stop" }));
+
+    let body: String = events
+        .iter()
+        .map(|event| {
+            format!(
+                "event: {}\ndata: {event}\n\n",
+                event["type"].as_str().unwrap()
+            )
+        })
+        .collect();
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/event-stream")],
+        body,
+    )
+        .into_response()
+}
@@ -0,0 +1,440 @@
+//! Renders ESC/POS output to a bitmap, so layouts can be checked without
+//! printing them.
+//!
+//! This understands the subset of commands the `epson` crate emits, plus
+//! `GS ( k` QR codes and `GS k` barcodes. Other commands are skipped if
+//! their length is known and are an error otherwise, since guessing would
+//! garble everything after them. Text is drawn with an 8x8 bitmap font
+//! scaled up to the printer's 12x24 Font A cell, so it's an approximation of
+//! the real output, not a pixel-perfect copy.
+
+use font8x8::UnicodeFonts;
+
+const CHAR_WIDTH: u32 = 12;
+const CHAR_HEIGHT: u32 = 24;
+const LINE_HEIGHT: u32 = 30;
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+const FS: u8 = 0x1c;
+const LF: u8 = b'\n';
+
+/// The length of each `ESC` command we know, including the `ESC`, for the
+/// ones with fixed-length parameters. Anything else can't be skipped safely.
+fn esc_len(cmd: u8) -> Option<usize> {
+    match cmd {
+        b'@' | b'i' | b'm' | b'2' => Some(2),
+        b'-' | b'E' | b'G' | b'B' | b'a' | b'd' | b'J' | b'!' | b'M' | b't' | b'R' | b'{'
+        | b'3' | b' ' | b'V' => Some(3),
+        // `ESC c 3 n`, `ESC c 4 n` and `ESC c 5 n` select paper sensors
+        // and panel buttons.
+        b'$' | b'\\' | b'c' => Some(4),
+        b'p' => Some(5),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    underline: bool,
+    emphasize: bool,
+    reverse: bool,
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn grow_to(&mut self, height: u32) {
+        if height > self.height {
+            self.pixels.resize((self.width * height) as usize, 255);
+            self.height = height;
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, black: bool) {
+        if x >= self.width {
+            return;
+        }
+        self.grow_to(y + 1);
+        self.pixels[(y * self.width + x) as usize] = if black { 0 } else { 255 };
+    }
+}
+
+struct Renderer {
+    canvas: Canvas,
+    y: u32,
+    style: Style,
+    alignment: epson::Alignment,
+    line: Vec<(char, Style)>,
+    pending: Vec<u8>,
+    qr: QrSettings,
+    barcode: BarcodeSettings,
+}
+
+/// Set by `GS H`, `GS h` and `GS w`.
+struct BarcodeSettings {
+    /// Whether the text goes below the bars.
+    text_below: bool,
+    height: u32,
+    module_width: u32,
+}
+
+/// State set up by `GS ( k` before a QR code is printed.
+struct QrSettings {
+    module_size: u32,
+    ec_level: qrcode::EcLevel,
+    data: Vec<u8>,
+}
+
+impl Renderer {
+    fn columns(&self) -> usize {
+        (self.canvas.width / CHAR_WIDTH) as usize
+    }
+
+    fn line_start(&self, content_width: u32) -> u32 {
+        let slack = self.canvas.width.saturating_sub(content_width);
+        match self.alignment {
+            epson::Alignment::Left => 0,
+            epson::Alignment::Center => slack / 2,
+            epson::Alignment::Right => slack,
+        }
+    }
+
+    fn push_text(&mut self) {
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending.clear();
+        for c in text.chars() {
+            if self.line.len() == self.columns() {
+                self.flush_line();
+            }
+            self.line.push((c, self.style));
+        }
+    }
+
+    fn flush_line(&mut self) {
+        self.push_text_if_pending();
+        let line = std::mem::take(&mut self.line);
+        let x0 = self.line_start(line.len() as u32 * CHAR_WIDTH);
+        self.canvas.grow_to(self.y + LINE_HEIGHT);
+        for (i, (c, style)) in line.into_iter().enumerate() {
+            self.draw_char(x0 + i as u32 * CHAR_WIDTH, c, style);
+        }
+        self.y += LINE_HEIGHT;
+    }
+
+    fn push_text_if_pending(&mut self) {
+        if !self.pending.is_empty() {
+            self.push_text();
+        }
+    }
+
+    fn draw_char(&mut self, x0: u32, c: char, style: Style) {
+        let glyph = font8x8::BASIC_FONTS
+            .get(c)
+            .or_else(|| font8x8::LATIN_FONTS.get(c))
+            .or_else(|| font8x8::BASIC_FONTS.get('?'))
+            .unwrap();
+        // Scale the 8x8 glyph into the upper part of the cell, leaving room
+        // at the bottom for the underline.
+        let lit = |dx: u32, dy: u32| {
+            let gx = dx * 8 / CHAR_WIDTH;
+            let Some(gy) = dy.checked_sub(2).map(|dy| dy * 8 / 20) else {
+                return false;
+            };
+            gy < 8 && glyph[gy as usize] & (1 << gx) != 0
+        };
+        for dy in 0..CHAR_HEIGHT {
+            for dx in 0..CHAR_WIDTH {
+                let mut black = lit(dx, dy) || (style.emphasize && dx > 0 && lit(dx - 1, dy));
+                if style.underline && dy >= CHAR_HEIGHT - 2 {
+                    black = true;
+                }
+                if style.reverse {
+                    black = !black;
+                }
+                self.canvas.set(x0 + dx, self.y + dy, black);
+            }
+        }
+    }
+
+    fn feed(&mut self, lines: u8) {
+        for _ in 0..lines {
+            self.flush_line();
+        }
+    }
+
+    fn cut(&mut self) {
+        self.push_text_if_pending();
+        if !self.line.is_empty() {
+            self.flush_line();
+        }
+        let y = self.y + LINE_HEIGHT / 2;
+        for x in 0..self.canvas.width {
+            self.canvas.set(x, y, x % 16 < 8);
+        }
+        self.y += LINE_HEIGHT;
+        self.canvas.grow_to(self.y);
+    }
+
+    fn image(&mut self, width: u32, height: u32, black: impl Fn(u32, u32) -> bool) {
+        self.push_text_if_pending();
+        if !self.line.is_empty() {
+            self.flush_line();
+        }
+        let x0 = self.line_start(width);
+        self.canvas.grow_to(self.y + height);
+        for y in 0..height {
+            for x in 0..width {
+                if black(x, y) {
+                    self.canvas.set(x0 + x, self.y + y, true);
+                }
+            }
+        }
+        self.y += height;
+    }
+
+    /// Draw a barcode from `GS k`. The bars only stand in for the real
+    /// symbology: each character gets 11 modules patterned after its bits.
+    fn barcode(&mut self, data: &[u8]) {
+        // Code 128 data starts with the code set, e.g. `{B`, and has any
+        // literal `{` doubled.
+        let text = match data.strip_prefix(b"{") {
+            Some(rest) => {
+                let mut text = Vec::new();
+                let mut bytes = rest.get(1..).unwrap_or_default().iter();
+                while let Some(&b) = bytes.next() {
+                    text.push(b);
+                    if b == b'{' {
+                        bytes.next();
+                    }
+                }
+                text
+            }
+            None => data.to_vec(),
+        };
+        let w = self.barcode.module_width.max(1);
+        let modules: Vec<bool> = text
+            .iter()
+            .flat_map(|&b| (0..11).map(move |k| k == 0 || (b >> (k % 8)) & 1 == 1))
+            .collect();
+        self.image(modules.len() as u32 * w, self.barcode.height, |x, _| {
+            modules[(x / w) as usize]
+        });
+        if self.barcode.text_below {
+            self.pending.extend(&text);
+            self.flush_line();
+        }
+    }
+
+    fn raster(&mut self, width_bytes: u32, height: u32, data: &[u8]) {
+        self.image(width_bytes * 8, height, |x, y| {
+            data[(y * width_bytes + x / 8) as usize] & (0x80 >> (x % 8)) != 0
+        });
+    }
+
+    /// Handle the parameters of a `GS ( k` command, starting from `cn`.
+    fn symbol(&mut self, params: &[u8]) -> anyhow::Result<()> {
+        // Only QR codes (cn = 49) are supported.
+        let [49, f, args @ ..] = params else {
+            tracing::debug!("Ignoring unknown 2D symbol in preview");
+            return Ok(());
+        };
+        match (f, args) {
+            (67, [n, ..]) => self.qr.module_size = (*n).into(),
+            (69, [n, ..]) => {
+                self.qr.ec_level = match n {
+                    48 => qrcode::EcLevel::L,
+                    50 => qrcode::EcLevel::Q,
+                    51 => qrcode::EcLevel::H,
+                    _ => qrcode::EcLevel::M,
+                }
+            }
+            (80, [_, data @ ..]) => self.qr.data = data.to_vec(),
+            (81, _) => {
+                let img =
+                    crate::receipt::qr_image(&self.qr.data, self.qr.module_size, self.qr.ec_level)?;
+                self.image(img.width(), img.height(), |x, y| {
+                    img.get_pixel(x, y).0[0] < 128
+                });
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Render raw ESC/POS bytes to a greyscale image `width` dots wide.
+pub fn render(bytes: &[u8], width: u32) -> anyhow::Result<image::GrayImage> {
+    let default_style = Style {
+        underline: false,
+        emphasize: false,
+        reverse: false,
+    };
+    let mut r = Renderer {
+        canvas: Canvas {
+            width,
+            height: 0,
+            pixels: Vec::new(),
+        },
+        y: 0,
+        style: default_style,
+        alignment: epson::Alignment::Left,
+        line: Vec::new(),
+        pending: Vec::new(),
+        barcode: BarcodeSettings {
+            text_below: false,
+            height: 162,
+            module_width: 3,
+        },
+        qr: QrSettings {
+            module_size: 3,
+            ec_level: qrcode::EcLevel::L,
+            data: Vec::new(),
+        },
+    };
+
+    let truncated = || anyhow::anyhow!("Truncated ESC/POS command");
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            ESC => {
+                let cmd = *bytes.get(i + 1).ok_or_else(truncated)?;
+                let len = esc_len(cmd).ok_or_else(|| {
+                    anyhow::anyhow!("Unsupported ESC command {cmd:#04x} in preview")
+                })?;
+                let params = bytes.get(i + 2..i + len).ok_or_else(truncated)?;
+                r.push_text_if_pending();
+                match (cmd, params) {
+                    (b'@', _) => {
+                        r.style = default_style;
+                        r.alignment = epson::Alignment::Left;
+                    }
+                    (b'i' | b'm', _) => r.cut(),
+                    (b'-', [n]) => r.style.underline = *n != 0,
+                    (b'E' | b'G', [n]) => r.style.emphasize = *n != 0,
+                    (b'B', [n]) => r.style.reverse = *n != 0,
+                    (b'a', [n]) => {
+                        r.alignment = match n {
+                            1 | b'1' => epson::Alignment::Center,
+                            2 | b'2' => epson::Alignment::Right,
+                            _ => epson::Alignment::Left,
+                        }
+                    }
+                    (b'd', [n]) => r.feed(*n),
+                    _ => tracing::debug!(cmd, "Ignoring ESC command in preview"),
+                }
+                i += len;
+            }
+            GS | FS => {
+                let cmd = *bytes.get(i + 1).ok_or_else(truncated)?;
+                match cmd {
+                    // Commands of the form `GS ( fn pL pH data...`
+                    b'(' => {
+                        let len = u16::from_le_bytes([
+                            *bytes.get(i + 3).ok_or_else(truncated)?,
+                            *bytes.get(i + 4).ok_or_else(truncated)?,
+                        ]) as usize;
+                        if bytes[i] == GS && bytes[i + 2] == b'k' {
+                            r.push_text_if_pending();
+                            r.symbol(bytes.get(i + 5..i + 5 + len).ok_or_else(truncated)?)?;
+                        }
+                        i += 5 + len;
+                    }
+                    b'v' => {
+                        let header = bytes.get(i + 4..i + 8).ok_or_else(truncated)?;
+                        let width_bytes = u16::from_le_bytes([header[0], header[1]]) as u32;
+                        let height = u16::from_le_bytes([header[2], header[3]]) as u32;
+                        let len = (width_bytes * height) as usize;
+                        let data = bytes.get(i + 8..i + 8 + len).ok_or_else(truncated)?;
+                        r.raster(width_bytes, height, data);
+                        i += 8 + len;
+                    }
+                    // `GS k m d1...dk NUL` for m up to 6, `GS k m n d1...dn`
+                    // after that.
+                    b'k' if bytes[i] == GS => {
+                        let m = *bytes.get(i + 2).ok_or_else(truncated)?;
+                        let (data, len) = if m <= 6 {
+                            let rest = bytes.get(i + 3..).unwrap_or_default();
+                            let end = rest.iter().position(|&b| b == 0).ok_or_else(truncated)?;
+                            (&rest[..end], 4 + end)
+                        } else {
+                            let n = *bytes.get(i + 3).ok_or_else(truncated)? as usize;
+                            (bytes.get(i + 4..i + 4 + n).ok_or_else(truncated)?, 4 + n)
+                        };
+                        r.push_text_if_pending();
+                        r.barcode(data);
+                        i += len;
+                    }
+                    b'H' | b'h' | b'w' if bytes[i] == GS => {
+                        let n = *bytes.get(i + 2).ok_or_else(truncated)?;
+                        match cmd {
+                            b'H' => r.barcode.text_below = matches!(n, 2 | 3 | b'2' | b'3'),
+                            b'h' => r.barcode.height = n.into(),
+                            _ => r.barcode.module_width = n.into(),
+                        }
+                        i += 3;
+                    }
+                    b'f' | b'!' | b'B' if bytes[i] == GS => i += 3,
+                    b'L' | b'W' if bytes[i] == GS => i += 4,
+                    b'.' | b'&' if bytes[i] == FS => i += 2,
+                    _ => anyhow::bail!(
+                        "Unsupported {} command {cmd:#04x} in preview",
+                        if bytes[i] == GS { "GS" } else { "FS" }
+                    ),
+                }
+            }
+            LF => {
+                r.flush_line();
+                i += 1;
+            }
+            b => {
+                r.pending.push(b);
+                i += 1;
+            }
+        }
+    }
+    r.push_text_if_pending();
+    if !r.line.is_empty() {
+        r.flush_line();
+    }
+
+    image::GrayImage::from_raw(r.canvas.width, r.canvas.height, r.canvas.pixels)
+        .ok_or_else(|| anyhow::anyhow!("Error building preview image"))
+}
+
+/// Renders jobs to a PNG file instead of printing them. Each job is drawn
+/// below the ones before it, like on the paper.
+pub struct PreviewSink {
+    path: std::path::PathBuf,
+    width: u32,
+    printed: std::sync::Mutex<Vec<u8>>,
+}
+
+impl PreviewSink {
+    pub fn new(path: impl Into<std::path::PathBuf>, width: u32) -> Self {
+        PreviewSink {
+            path: path.into(),
+            width,
+            printed: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl crate::printer::PrintSink for PreviewSink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> crate::printer::BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let printed = {
+                let mut printed = self.printed.lock().unwrap();
+                printed.extend_from_slice(job);
+                printed.clone()
+            };
+            let img = render(&printed, self.width)?;
+            img.save_with_format(&self.path, image::ImageFormat::Png)?;
+            Ok(())
+        })
+    }
+}
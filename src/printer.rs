@@ -1,9 +1,272 @@
 use epson::AsyncWriterExt;
+use tokio::io::AsyncWriteExt;
 
-pub async fn new_epson_writer() -> anyhow::Result<epson::Writer<impl tokio::io::AsyncWrite>> {
-    let stream = tokio::net::TcpStream::connect("192.168.7.238:9100").await?;
-    let mut w = epson::Writer::open(epson::Model::T30II, stream).await?;
-    w.set_unicode().await?;
-    w.speed(5).await?;
-    Ok(w)
+#[derive(serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+#[value(rename_all = "lowercase")]
+pub enum Model {
+    Generic,
+    T20II,
+    T30II,
+}
+
+impl Model {
+    /// Whether the printer can draw QR codes itself with `GS ( k`.
+    pub fn supports_qr(self) -> bool {
+        match self {
+            Model::Generic => false,
+            Model::T20II | Model::T30II => true,
+        }
+    }
+
+    /// Whether the printer can be switched into unicode mode.
+    pub fn supports_unicode(self) -> bool {
+        epson::Model::from(self).supports_character_set(epson::CharacterSet::Unicode)
+    }
+}
+
+impl From<Model> for epson::Model {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Generic => epson::Model::Generic,
+            Model::T20II => epson::Model::T20II,
+            Model::T30II => epson::Model::T30II,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum PaperWidth {
+    #[serde(rename = "58mm")]
+    #[value(name = "58mm")]
+    Mm58,
+    #[default]
+    #[serde(rename = "80mm")]
+    #[value(name = "80mm")]
+    Mm80,
+}
+
+impl PaperWidth {
+    /// The printable width in dots.
+    pub fn dots(self) -> u32 {
+        match self {
+            PaperWidth::Mm58 => 384,
+            PaperWidth::Mm80 => 576,
+        }
+    }
+
+    /// How many characters of the default 12x24 font fit on a line.
+    pub fn columns(self) -> usize {
+        (self.dots() / 12) as usize
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PrinterConfig {
+    pub host: String,
+    pub port: u16,
+    pub model: Model,
+    pub paper_width: PaperWidth,
+    pub speed: u8,
+    /// Defaults to whether the model supports it.
+    pub unicode: Option<bool>,
+}
+
+impl PrinterConfig {
+    pub fn unicode(&self) -> bool {
+        self.unicode
+            .unwrap_or_else(|| self.model.supports_unicode())
+    }
+}
+
+impl Default for PrinterConfig {
+    fn default() -> Self {
+        PrinterConfig {
+            host: "192.168.7.238".to_string(),
+            port: 9100,
+            model: Model::T30II,
+            paper_width: PaperWidth::Mm80,
+            speed: 5,
+            unicode: None,
+        }
+    }
+}
+
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Somewhere a complete, rendered ESC/POS job can be sent.
+pub trait PrintSink: Send + Sync {
+    fn print<'a>(&'a self, job: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Ask the printer for its status. Sinks that aren't a real printer
+    /// return `None`.
+    fn status(&self) -> BoxFuture<'_, anyhow::Result<Option<crate::status::PrinterStatus>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Sends jobs to a network printer over a single connection, which is kept
+/// open between jobs and status checks and only reopened after an error.
+/// The printer's [crate::queue::PrintActor] is its only user, so the
+/// connection is never shared between tasks.
+pub struct TcpSink {
+    host: String,
+    port: u16,
+    connection: tokio::sync::Mutex<Option<tokio::net::TcpStream>>,
+}
+
+impl TcpSink {
+    pub fn new(config: &PrinterConfig) -> Self {
+        TcpSink {
+            host: config.host.clone(),
+            port: config.port,
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn connect<'a>(
+        &self,
+        connection: &'a mut Option<tokio::net::TcpStream>,
+    ) -> anyhow::Result<&'a mut tokio::net::TcpStream> {
+        if connection.is_none() {
+            let stream = tokio::net::TcpStream::connect((self.host.as_str(), self.port)).await?;
+            *connection = Some(stream);
+        }
+        Ok(connection.as_mut().unwrap())
+    }
+}
+
+impl PrintSink for TcpSink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut connection = self.connection.lock().await;
+            let result = async {
+                let stream = self.connect(&mut connection).await?;
+                stream.write_all(job).await?;
+                stream.flush().await?;
+                anyhow::Ok(())
+            }
+            .await;
+            if result.is_err() {
+                *connection = None;
+            }
+            result
+        })
+    }
+
+    fn status(&self) -> BoxFuture<'_, anyhow::Result<Option<crate::status::PrinterStatus>>> {
+        Box::pin(async move {
+            let mut connection = self.connection.lock().await;
+            // This also finds out if the printer dropped the connection, so
+            // the next job goes out over a fresh one.
+            let result = async {
+                let stream = self.connect(&mut connection).await?;
+                crate::status::query(stream).await
+            }
+            .await;
+            if result.is_err() {
+                *connection = None;
+            }
+            Ok(Some(result?))
+        })
+    }
+}
+
+/// Appends the raw ESC/POS bytes of each job to a file.
+pub struct FileSink {
+    path: std::path::PathBuf,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<std::path::PathBuf>) -> Self {
+        FileSink { path: path.into() }
+    }
+}
+
+impl PrintSink for FileSink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut f = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            f.write_all(job).await?;
+            f.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+/// Writes the raw ESC/POS bytes of each job to stdout.
+pub struct StdoutSink;
+
+impl PrintSink for StdoutSink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(job).await?;
+            stdout.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+/// Keeps every job in memory, for tests.
+#[derive(Default)]
+pub struct MemorySink {
+    jobs: std::sync::Mutex<Vec<Vec<u8>>>,
+}
+
+impl MemorySink {
+    pub fn jobs(&self) -> Vec<Vec<u8>> {
+        self.jobs.lock().unwrap().clone()
+    }
+}
+
+impl PrintSink for MemorySink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.jobs.lock().unwrap().push(job.to_vec());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Clone)]
+pub struct Printer {
+    config: PrinterConfig,
+    sink: std::sync::Arc<dyn PrintSink>,
+}
+
+impl Printer {
+    pub fn new(config: PrinterConfig, sink: std::sync::Arc<dyn PrintSink>) -> Self {
+        Printer { config, sink }
+    }
+
+    pub fn config(&self) -> &PrinterConfig {
+        &self.config
+    }
+
+    /// Render a receipt to the raw bytes this printer expects.
+    pub async fn render(&self, receipt: &crate::receipt::Receipt) -> anyhow::Result<Vec<u8>> {
+        let mut w = epson::Writer::open(self.config.model.into(), Vec::new()).await?;
+        if self.config.unicode() {
+            w.set_unicode().await?;
+        }
+        w.speed(self.config.speed).await?;
+        crate::receipt::write_receipt(&mut w, receipt, &self.config).await?;
+        Ok(w.into_inner())
+    }
+
+    pub async fn print(&self, job: &[u8]) -> anyhow::Result<()> {
+        self.sink.print(job).await
+    }
+
+    pub async fn status(&self) -> anyhow::Result<Option<crate::status::PrinterStatus>> {
+        self.sink.status().await
+    }
+
+    pub async fn print_receipt(&self, receipt: &crate::receipt::Receipt) -> anyhow::Result<()> {
+        self.print(&self.render(receipt).await?).await
+    }
 }
@@ -0,0 +1,411 @@
+//! A durable queue of rendered print jobs.
+//!
+//! Each job is stored as two files in the queue directory: `<id>.job` holds
+//! the raw bytes and `<id>.json` holds its status. The bytes are deleted once
+//! a job prints; the status file is kept so callers can look the job up
+//! afterwards.
+//!
+//! Each printer has its own queue. A single [PrintActor] owns the printer
+//! and its connection. Submitted jobs reach it over a channel, and it prints
+//! them one at a time, highest priority first, retrying with backoff while
+//! the printer is unreachable. Nothing else talks to the printer, so
+//! concurrent jobs can't interleave.
+//!
+//! The actor also polls the printer's status, and holds jobs while the
+//! printer reports it's out of paper.
+
+use anyhow::Context;
+
+const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+const STATUS_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const HOLD_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Printed,
+}
+
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct JobStatus {
+    pub id: String,
+    #[serde(default)]
+    pub printer: String,
+    pub state: JobState,
+    #[serde(default)]
+    pub priority: Priority,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub printed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+struct QueuedJob {
+    id: String,
+    priority: Priority,
+    data: Vec<u8>,
+}
+
+// Highest priority first, then oldest (lowest ID) first.
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for QueuedJob {}
+
+struct QueueDir(std::path::PathBuf);
+
+impl QueueDir {
+    fn job_path(&self, id: &str) -> std::path::PathBuf {
+        self.0.join(format!("{id}.job"))
+    }
+
+    fn status_path(&self, id: &str) -> std::path::PathBuf {
+        self.0.join(format!("{id}.json"))
+    }
+
+    async fn write_atomically(path: &std::path::Path, contents: &[u8]) -> anyhow::Result<()> {
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, contents).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
+    async fn save_status(&self, status: &JobStatus) -> anyhow::Result<()> {
+        Self::write_atomically(
+            &self.status_path(&status.id),
+            &serde_json::to_vec_pretty(status)?,
+        )
+        .await
+    }
+
+    async fn load_status(&self, id: &str) -> anyhow::Result<Option<JobStatus>> {
+        match tokio::fs::read(self.status_path(id)).await {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Load every job that was queued but hadn't printed yet.
+    fn load_pending(&self) -> anyhow::Result<Vec<QueuedJob>> {
+        let mut jobs = Vec::new();
+        for entry in std::fs::read_dir(&self.0)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "job") {
+                continue;
+            }
+            let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let priority = match std::fs::read(self.status_path(id)) {
+                Ok(contents) => {
+                    let status: JobStatus = serde_json::from_slice(&contents)?;
+                    // It printed, but deleting the bytes afterwards failed.
+                    if status.state == JobState::Printed {
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    status.priority
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Priority::default(),
+                Err(e) => return Err(e.into()),
+            };
+            jobs.push(QueuedJob {
+                id: id.to_string(),
+                priority,
+                data: std::fs::read(&path)?,
+            });
+        }
+        Ok(jobs)
+    }
+}
+
+/// A handle for submitting jobs and checking on them.
+pub struct PrintQueue {
+    name: String,
+    dir: std::sync::Arc<QueueDir>,
+    printer: crate::printer::Printer,
+    tx: tokio::sync::mpsc::Sender<QueuedJob>,
+    status_rx: tokio::sync::watch::Receiver<Option<crate::status::StatusReport>>,
+}
+
+/// The task that owns the printer. Run it with [PrintActor::run].
+pub struct PrintActor {
+    name: String,
+    dir: std::sync::Arc<QueueDir>,
+    printer: crate::printer::Printer,
+    rx: tokio::sync::mpsc::Receiver<QueuedJob>,
+    status_tx: tokio::sync::watch::Sender<Option<crate::status::StatusReport>>,
+    pending: std::collections::BinaryHeap<QueuedJob>,
+}
+
+impl PrintQueue {
+    pub fn open(
+        name: impl Into<String>,
+        dir: impl Into<std::path::PathBuf>,
+        printer: crate::printer::Printer,
+    ) -> anyhow::Result<(std::sync::Arc<Self>, PrintActor)> {
+        let name = name.into();
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Error creating queue directory {}", dir.display()))?;
+        let dir = std::sync::Arc::new(QueueDir(dir));
+        // Load leftovers from a previous run before anything new can be
+        // submitted, so no job is both loaded and received.
+        let pending = dir
+            .load_pending()
+            .context("Error loading pending print jobs")?;
+        let (tx, rx) = tokio::sync::mpsc::channel(32);
+        let (status_tx, status_rx) = tokio::sync::watch::channel(None);
+        let queue = std::sync::Arc::new(PrintQueue {
+            name: name.clone(),
+            dir: dir.clone(),
+            printer: printer.clone(),
+            tx,
+            status_rx,
+        });
+        let actor = PrintActor {
+            name,
+            dir,
+            printer,
+            rx,
+            status_tx,
+            pending: pending.into_iter().collect(),
+        };
+        Ok((queue, actor))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn printer(&self) -> &crate::printer::Printer {
+        &self.printer
+    }
+
+    /// The printer status from the actor's most recent check, if it has
+    /// checked yet.
+    pub fn printer_status(&self) -> Option<crate::status::StatusReport> {
+        self.status_rx.borrow().clone()
+    }
+
+    /// IDs are the submission time in microseconds, so sorting them gives
+    /// the order jobs were submitted in. They're unique across every queue,
+    /// so a job can be looked up without knowing its printer.
+    fn new_id() -> String {
+        static LAST_ID: std::sync::Mutex<i64> = std::sync::Mutex::new(0);
+        let mut last_id = LAST_ID.lock().unwrap();
+        *last_id = chrono::Utc::now().timestamp_micros().max(*last_id + 1);
+        format!("{:016x}", *last_id)
+    }
+
+    /// Persist a rendered job and hand it to the printer actor. Returns the
+    /// job's ID.
+    pub async fn submit(&self, data: Vec<u8>, priority: Priority) -> anyhow::Result<String> {
+        let id = Self::new_id();
+        QueueDir::write_atomically(&self.dir.job_path(&id), &data).await?;
+        self.dir
+            .save_status(&JobStatus {
+                id: id.clone(),
+                printer: self.name.clone(),
+                state: JobState::Pending,
+                priority,
+                created_at: chrono::Utc::now(),
+                attempts: 0,
+                last_error: None,
+                printed_at: None,
+            })
+            .await?;
+        self.tx
+            .send(QueuedJob {
+                id: id.clone(),
+                priority,
+                data,
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("Printer actor has stopped"))?;
+        Ok(id)
+    }
+
+    pub async fn submit_receipt(
+        &self,
+        receipt: &crate::receipt::Receipt,
+        priority: Priority,
+    ) -> anyhow::Result<String> {
+        self.submit(self.printer.render(receipt).await?, priority)
+            .await
+    }
+
+    pub async fn status(&self, id: &str) -> anyhow::Result<Option<JobStatus>> {
+        // IDs end up in file paths, so don't let arbitrary strings through.
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        self.dir.load_status(id).await
+    }
+}
+
+impl PrintActor {
+    /// Try to print a single job, returning whether it printed. Errors from
+    /// recording the outcome are only logged, so a job that printed is never
+    /// retried.
+    async fn print_job(&self, job: &QueuedJob) -> anyhow::Result<bool> {
+        // The status file is written after the job, so it can be missing if
+        // we crashed in between.
+        let mut status = match self.dir.load_status(&job.id).await? {
+            Some(status) => status,
+            None => JobStatus {
+                id: job.id.clone(),
+                printer: self.name.clone(),
+                state: JobState::Pending,
+                priority: job.priority,
+                created_at: chrono::Utc::now(),
+                attempts: 0,
+                last_error: None,
+                printed_at: None,
+            },
+        };
+
+        status.attempts += 1;
+        let result = self.printer.print(&job.data).await;
+        match &result {
+            Ok(()) => {
+                status.state = JobState::Printed;
+                status.printed_at = Some(chrono::Utc::now());
+                status.last_error = None;
+            }
+            Err(err) => {
+                tracing::warn!(printer = self.name, job = job.id, attempts = status.attempts, error = ?err, "Print job failed");
+                status.last_error = Some(format!("{err:#}"));
+            }
+        }
+        if let Err(err) = self.record(&status).await {
+            tracing::error!(printer = self.name, job = job.id, error = ?err, "Failed to record print job status");
+        }
+        Ok(result.is_ok())
+    }
+
+    /// Save a job's status after an attempt, and drop its bytes if it
+    /// printed.
+    async fn record(&self, status: &JobStatus) -> anyhow::Result<()> {
+        self.dir.save_status(status).await?;
+        if status.state == JobState::Printed {
+            tokio::fs::remove_file(self.dir.job_path(&status.id)).await?;
+        }
+        Ok(())
+    }
+
+    async fn poll_status(&self) -> Option<crate::status::PrinterStatus> {
+        let result = self.printer.status().await;
+        if let Err(err) = &result {
+            tracing::debug!(printer = self.name, error = ?err, "Failed to get printer status");
+        }
+        let status = result.as_ref().ok().copied().flatten();
+        self.status_tx
+            .send_replace(Some(crate::status::StatusReport {
+                checked_at: chrono::Utc::now(),
+                status,
+                error: result.err().map(|err| format!("{err:#}")),
+            }));
+        status
+    }
+
+    /// Print jobs until every [PrintQueue] handle is dropped.
+    pub async fn run(mut self) {
+        let mut failures = 0;
+        let mut retry_at = None;
+        let mut next_poll = tokio::time::Instant::now();
+        loop {
+            while let Ok(job) = self.rx.try_recv() {
+                self.pending.push(job);
+            }
+
+            if tokio::time::Instant::now() >= next_poll {
+                self.poll_status().await;
+                next_poll = tokio::time::Instant::now() + STATUS_INTERVAL;
+            }
+
+            let ready = retry_at.is_none_or(|t| tokio::time::Instant::now() >= t);
+            if ready && let Some(job) = self.pending.pop() {
+                // Check right before printing, since the paper may have run
+                // out since the last poll. If we can't tell, try anyway.
+                if self.poll_status().await.is_some_and(|s| s.paper_end) {
+                    tracing::warn!(
+                        printer = self.name,
+                        pending = self.pending.len() + 1,
+                        "Printer is out of paper, holding jobs"
+                    );
+                    retry_at = Some(tokio::time::Instant::now() + HOLD_INTERVAL);
+                    self.pending.push(job);
+                    continue;
+                }
+                match self.print_job(&job).await {
+                    Ok(true) => {
+                        failures = 0;
+                        retry_at = None;
+                    }
+                    Ok(false) => {
+                        failures += 1;
+                        let backoff = std::time::Duration::from_secs(1 << failures.min(16));
+                        retry_at = Some(tokio::time::Instant::now() + backoff.min(MAX_BACKOFF));
+                        self.pending.push(job);
+                    }
+                    // Only before printing, e.g. the status file is unreadable.
+                    Err(err) => {
+                        tracing::error!(printer = self.name, job = job.id, error = ?err, "Failed to process print job");
+                        retry_at = Some(tokio::time::Instant::now() + MAX_BACKOFF);
+                        self.pending.push(job);
+                    }
+                }
+                continue;
+            }
+
+            let wake_at = retry_at.unwrap_or(next_poll).min(next_poll);
+            tokio::select! {
+                job = self.rx.recv() => match job {
+                    Some(job) => self.pending.push(job),
+                    None => return,
+                },
+                _ = tokio::time::sleep_until(wake_at) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use epson::AsyncWriterExt;
+
+/// A printable document, independent of how it ends up on paper.
+#[derive(Clone, Debug, Default)]
+pub struct Receipt {
+    elements: Vec<Element>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Element {
+    /// Centered and underlined.
+    Title(String),
+    /// Centered.
+    Centered(String),
+    /// Underlined, on its own line.
+    Heading(String),
+    /// An underlined label followed by its value on the same line.
+    Field {
+        label: String,
+        value: String,
+    },
+    Checklist(Vec<ChecklistItem>),
+    Paragraph(String),
+    Separator,
+    /// Scaled to the paper width and dithered when rendered.
+    Image(image::GrayImage),
+    /// A centered QR code encoding the text, e.g. a link.
+    Qr(String),
+    /// A centered Code 128 barcode with the text printed under it. Only
+    /// printable ASCII can be encoded.
+    Barcode(String),
+    Feed(u8),
+    Cut,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChecklistItem {
+    /// Printed in bold ahead of the text, e.g. a due time.
+    pub highlight: Option<String>,
+    pub text: String,
+    /// Printed as a QR code under the item.
+    pub link: Option<String>,
+}
+
+impl Receipt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    pub fn push(mut self, element: Element) -> Self {
+        self.elements.push(element);
+        self
+    }
+
+    pub fn title(self, text: impl Into<String>) -> Self {
+        self.push(Element::Title(text.into()))
+    }
+
+    pub fn centered(self, text: impl Into<String>) -> Self {
+        self.push(Element::Centered(text.into()))
+    }
+
+    pub fn heading(self, text: impl Into<String>) -> Self {
+        self.push(Element::Heading(text.into()))
+    }
+
+    pub fn field(self, label: impl Into<String>, value: impl Into<String>) -> Self {
+        self.push(Element::Field {
+            label: label.into(),
+            value: value.into(),
+        })
+    }
+
+    pub fn checklist(self, items: Vec<ChecklistItem>) -> Self {
+        self.push(Element::Checklist(items))
+    }
+
+    pub fn paragraph(self, text: impl Into<String>) -> Self {
+        self.push(Element::Paragraph(text.into()))
+    }
+
+    pub fn separator(self) -> Self {
+        self.push(Element::Separator)
+    }
+
+    pub fn image(self, img: image::GrayImage) -> Self {
+        self.push(Element::Image(img))
+    }
+
+    pub fn qr(self, data: impl Into<String>) -> Self {
+        self.push(Element::Qr(data.into()))
+    }
+
+    pub fn barcode(self, data: impl Into<String>) -> Self {
+        self.push(Element::Barcode(data.into()))
+    }
+
+    pub fn feed(self, lines: u8) -> Self {
+        self.push(Element::Feed(lines))
+    }
+
+    pub fn cut(self) -> Self {
+        self.push(Element::Cut)
+    }
+}
+
+/// Break `text` into lines of whole words, the first at most `first`
+/// columns wide and the rest at most `rest`. Words longer than a line are
+/// split. Newlines in `text` are kept.
+fn wrap(text: &str, first: usize, rest: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        let mut line_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut word = word;
+            loop {
+                let width = if lines.is_empty() { first } else { rest };
+                let word_len = word.chars().count();
+                if line.is_empty() && word_len <= width {
+                    line.push_str(word);
+                    line_len = word_len;
+                    break;
+                }
+                if !line.is_empty() && line_len + 1 + word_len <= width {
+                    line.push(' ');
+                    line.push_str(word);
+                    line_len += 1 + word_len;
+                    break;
+                }
+                if !line.is_empty() || width == 0 {
+                    lines.push(std::mem::take(&mut line));
+                    line_len = 0;
+                    continue;
+                }
+                let (split, _) = word.char_indices().nth(width).unwrap();
+                lines.push(word[..split].to_string());
+                word = &word[split..];
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Breaks text that arrives a piece at a time into the lines a
+/// [Element::Paragraph] of all of it would print, handing each line back once
+/// nothing more can be added to it.
+pub struct LineBreaker {
+    columns: usize,
+    text: String,
+    /// How many lines have been handed back.
+    done: usize,
+}
+
+impl LineBreaker {
+    pub fn new(columns: usize) -> Self {
+        LineBreaker {
+            columns,
+            text: String::new(),
+            done: 0,
+        }
+    }
+
+    /// Add `text`, returning any lines it finished.
+    pub fn push(&mut self, text: &str) -> Vec<String> {
+        self.text.push_str(text);
+        let mut lines = wrap(&self.text, self.columns, self.columns);
+        // The last line can still grow; the ones before it can't.
+        lines.pop();
+        let new = lines.split_off(self.done.min(lines.len()));
+        self.done += new.len();
+        new
+    }
+
+    /// The lines that are left once there's no more text.
+    pub fn finish(self) -> Vec<String> {
+        if self.text.trim().is_empty() {
+            return Vec::new();
+        }
+        let mut lines = wrap(&self.text, self.columns, self.columns);
+        lines.split_off(self.done.min(lines.len()))
+    }
+}
+
+async fn write_lines<W>(
+    w: &mut epson::Writer<W>,
+    lines: &[String],
+    indent: &str,
+) -> anyhow::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+{
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            w.write_all(indent.as_bytes()).await?;
+        }
+        w.write_all(format!("{line}\n").as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Size of each QR module in dots.
+const QR_MODULE_SIZE: u8 = 4;
+
+/// Draw `data` as a QR code with `module_size` dots per module, for printers
+/// that can't do it themselves.
+pub fn qr_image(
+    data: &[u8],
+    module_size: u32,
+    ec_level: qrcode::EcLevel,
+) -> anyhow::Result<image::GrayImage> {
+    Ok(qrcode::QrCode::with_error_correction_level(data, ec_level)?
+        .render::<image::Luma<u8>>()
+        .quiet_zone(false)
+        .module_dimensions(module_size, module_size)
+        .build())
+}
+
+async fn write_qr<W>(
+    w: &mut epson::Writer<W>,
+    data: &str,
+    model: crate::printer::Model,
+) -> anyhow::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+{
+    w.justify(epson::Alignment::Center).await?;
+    if model.supports_qr() {
+        // GS ( k pL pH cn fn [params], with cn = 49 for QR codes.
+        let gs_k = |f: u8, params: &[u8]| {
+            let len = (params.len() + 2) as u16;
+            let mut cmd = vec![0x1d, b'(', b'k'];
+            cmd.extend(len.to_le_bytes());
+            cmd.extend([49, f]);
+            cmd.extend(params);
+            cmd
+        };
+        // Model 2, module size, error correction level M, then store the
+        // data and print it.
+        w.write_all(&gs_k(65, &[50, 0])).await?;
+        w.write_all(&gs_k(67, &[QR_MODULE_SIZE])).await?;
+        w.write_all(&gs_k(69, &[49])).await?;
+        w.write_all(&gs_k(80, &[&[48], data.as_bytes()].concat()))
+            .await?;
+        w.write_all(&gs_k(81, &[48])).await?;
+    } else {
+        w.print_image(qr_image(
+            data.as_bytes(),
+            QR_MODULE_SIZE.into(),
+            qrcode::EcLevel::M,
+        )?)
+        .await?;
+    }
+    w.justify(epson::Alignment::Left).await?;
+    Ok(())
+}
+
+/// Height of barcodes in dots.
+const BARCODE_HEIGHT: u8 = 80;
+/// Width of a barcode's narrowest bar in dots.
+const BARCODE_MODULE_WIDTH: u8 = 2;
+
+async fn write_barcode<W>(w: &mut epson::Writer<W>, data: &str) -> anyhow::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+{
+    // `{` starts a code set or function code, so a literal one is sent as
+    // `{{`. The length byte also counts the `{B` that selects code set B.
+    let escaped = data.replace('{', "{{");
+    if data.is_empty() || escaped.len() > 253 || !data.bytes().all(|b| (0x20..0x7f).contains(&b)) {
+        anyhow::bail!("Can't print {data:?} as a Code 128 barcode");
+    }
+    w.justify(epson::Alignment::Center).await?;
+    // Text below the bars, then the bar height and width.
+    w.write_all(&[0x1d, b'H', 2]).await?;
+    w.write_all(&[0x1d, b'h', BARCODE_HEIGHT]).await?;
+    w.write_all(&[0x1d, b'w', BARCODE_MODULE_WIDTH]).await?;
+    // GS k m n d1...dn, with m = 73 for Code 128.
+    let mut cmd = vec![0x1d, b'k', 73, escaped.len() as u8 + 2, b'{', b'B'];
+    cmd.extend(escaped.as_bytes());
+    w.write_all(&cmd).await?;
+    w.justify(epson::Alignment::Left).await?;
+    Ok(())
+}
+
+/// Scale `img` to `width` dots and dither it to black and white.
+fn fit_image(img: &image::GrayImage, width: u32) -> image::GrayImage {
+    let mut img = if img.width() == width {
+        img.clone()
+    } else {
+        let height = (img.height() as u64 * width as u64 / img.width().max(1) as u64).max(1);
+        image::imageops::resize(
+            img,
+            width,
+            height as u32,
+            image::imageops::FilterType::Lanczos3,
+        )
+    };
+    image::imageops::colorops::dither(&mut img, &image::imageops::colorops::BiLevel);
+    img
+}
+
+pub async fn write_receipt<W>(
+    w: &mut epson::Writer<W>,
+    receipt: &Receipt,
+    config: &crate::printer::PrinterConfig,
+) -> anyhow::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+{
+    let columns = config.paper_width.columns();
+    let image_width = config
+        .paper_width
+        .dots()
+        .min(epson::Model::from(config.model).get_max_image_width() as u32);
+    for element in &receipt.elements {
+        match element {
+            Element::Title(text) => {
+                w.justify(epson::Alignment::Center).await?;
+                w.underline(true).await?;
+                write_lines(w, &wrap(text, columns, columns), "").await?;
+                w.underline(false).await?;
+                w.justify(epson::Alignment::Left).await?;
+            }
+            Element::Centered(text) => {
+                w.justify(epson::Alignment::Center).await?;
+                write_lines(w, &wrap(text, columns, columns), "").await?;
+                w.justify(epson::Alignment::Left).await?;
+            }
+            Element::Heading(text) => {
+                w.underline(true).await?;
+                write_lines(w, &wrap(text, columns, columns), "").await?;
+                w.underline(false).await?;
+            }
+            Element::Field { label, value } => {
+                let label = format!("{label}:");
+                w.underline(true).await?;
+                w.write_all(label.as_bytes()).await?;
+                w.underline(false).await?;
+                w.write_all(b" ").await?;
+                // The printer wraps the label itself if it's too long.
+                let used = (label.chars().count() + 1) % columns;
+                write_lines(w, &wrap(value, columns.saturating_sub(used), columns), "").await?;
+            }
+            Element::Checklist(items) => {
+                // Continuation lines line up under the text, past the "[ ] ".
+                const INDENT: &str = "    ";
+                let rest = columns - INDENT.len();
+                for item in items {
+                    w.write_all(b"[ ] ").await?;
+                    let mut first = rest;
+                    if let Some(highlight) = &item.highlight {
+                        w.emphasize(true).await?;
+                        w.write_all(highlight.as_bytes()).await?;
+                        w.emphasize(false).await?;
+                        w.write_all(b" ").await?;
+                        first = rest.saturating_sub(highlight.chars().count() + 1);
+                    }
+                    write_lines(w, &wrap(&item.text, first, rest), INDENT).await?;
+                    if let Some(link) = &item.link {
+                        write_qr(w, link, config.model).await?;
+                    }
+                }
+            }
+            Element::Paragraph(text) => {
+                write_lines(w, &wrap(text, columns, columns), "").await?;
+            }
+            Element::Separator => {
+                w.write_all(b"---\n").await?;
+            }
+            Element::Image(img) => {
+                w.print_image(fit_image(img, image_width)).await?;
+            }
+            Element::Qr(data) => {
+                write_qr(w, data, config.model).await?;
+            }
+            Element::Barcode(data) => {
+                write_barcode(w, data).await?;
+            }
+            Element::Feed(lines) => {
+                w.feed(*lines).await?;
+            }
+            Element::Cut => {
+                w.cut().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
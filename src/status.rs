@@ -0,0 +1,81 @@
+//! Real-time printer status via `DLE EOT n`.
+//!
+//! The printer answers each query with a single byte, even while it's
+//! offline, so this works when nothing else will print.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const DLE: u8 = 0x10;
+const EOT: u8 = 0x04;
+
+const QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// The `n` parameter for each `DLE EOT n` query, in the order they're sent.
+const PRINTER_STATUS: u8 = 1;
+const OFFLINE_CAUSE: u8 = 2;
+const ERROR_CAUSE: u8 = 3;
+const PAPER_SENSOR: u8 = 4;
+
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct PrinterStatus {
+    pub online: bool,
+    pub cover_open: bool,
+    pub paper_near_end: bool,
+    pub paper_end: bool,
+    pub cutter_error: bool,
+    pub unrecoverable_error: bool,
+    pub auto_recoverable_error: bool,
+}
+
+/// The result of the most recent status check.
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct StatusReport {
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    /// `None` if the printer can't report its status, e.g. when printing to
+    /// a file.
+    pub status: Option<PrinterStatus>,
+    pub error: Option<String>,
+}
+
+fn bit(byte: u8, n: u8) -> bool {
+    byte & (1 << n) != 0
+}
+
+/// Parse the responses to `DLE EOT 1` through `DLE EOT 4`.
+pub fn parse(responses: [u8; 4]) -> anyhow::Result<PrinterStatus> {
+    // Every response has the form 0xx1xx10.
+    if let Some(byte) = responses.iter().find(|&&b| b & 0b1001_0011 != 0b0001_0010) {
+        anyhow::bail!("Unexpected status response from printer: {byte:#04x}");
+    }
+    let [printer, offline, error, paper] = responses;
+    Ok(PrinterStatus {
+        online: !bit(printer, 3),
+        cover_open: bit(offline, 2),
+        // Paper end shows up both as the reason printing stopped and on the
+        // roll sensor; the sensor reports it with two bits set.
+        paper_end: bit(offline, 5) || (bit(paper, 5) && bit(paper, 6)),
+        paper_near_end: bit(paper, 2) && bit(paper, 3),
+        cutter_error: bit(error, 2),
+        unrecoverable_error: bit(error, 3),
+        auto_recoverable_error: bit(error, 5),
+    })
+}
+
+/// Send the status queries over `stream` and parse the printer's answers.
+pub async fn query<S>(stream: &mut S) -> anyhow::Result<PrinterStatus>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let mut responses = [0; 4];
+    for (response, n) in
+        responses
+            .iter_mut()
+            .zip([PRINTER_STATUS, OFFLINE_CAUSE, ERROR_CAUSE, PAPER_SENSOR])
+    {
+        stream.write_all(&[DLE, EOT, n]).await?;
+        *response = tokio::time::timeout(QUERY_TIMEOUT, stream.read_u8())
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out waiting for printer status"))??;
+    }
+    parse(responses)
+}
@@ -5,6 +5,7 @@ struct TodoistResponse {
 
 #[derive(serde::Deserialize)]
 struct TodoistTask {
+    id: String,
     content: String,
     due: Option<TodoistDue>,
 }
@@ -17,6 +18,8 @@ struct TodoistDue {
 pub struct TodoItem {
     pub content: String,
     pub time: Option<chrono::NaiveTime>,
+    /// Opens the task in Todoist.
+    pub url: Option<String>,
 }
 
 fn parse_due_time(s: &str) -> Option<chrono::NaiveTime> {
@@ -54,6 +57,7 @@ pub async fn get_todo_items(
         .map(|t| TodoItem {
             content: t.content,
             time: t.due.as_ref().and_then(|d| parse_due_time(&d.date)),
+            url: Some(format!("https://app.todoist.com/app/task/{}", t.id)),
         })
         .collect())
 }
@@ -0,0 +1,363 @@
+//! Token usage and spend for LLM calls.
+//!
+//! Every successful call appends a line to a JSONL ledger, tagged with the
+//! feature that made it. The ledger is shared by `adb` runs and the gram
+//! server, and is what the budget caps are checked against.
+
+use anyhow::Context;
+use chrono::Datelike;
+
+/// Token counts as the Messages API reports them. Thinking is billed as
+/// output and included in `output_tokens`; the API doesn't break it out.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default)]
+pub struct Usage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default, deserialize_with = "null_as_zero")]
+    pub cache_creation_input_tokens: u64,
+    #[serde(default, deserialize_with = "null_as_zero")]
+    pub cache_read_input_tokens: u64,
+}
+
+fn null_as_zero<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(<Option<u64> as serde::Deserialize>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+/// The feature an LLM call was made for.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum CallSite {
+    Brief,
+    Gram,
+    Court,
+    Other,
+}
+
+/// USD per million tokens.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct Prices {
+    pub input: f64,
+    pub output: f64,
+    pub cache_write: f64,
+    pub cache_read: f64,
+}
+
+impl Prices {
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_creation_input_tokens as f64 * self.cache_write
+            + usage.cache_read_input_tokens as f64 * self.cache_read)
+            / 1_000_000.0
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct UsageConfig {
+    pub ledger: std::path::PathBuf,
+    /// Stop making LLM calls once this many USD have been spent today.
+    pub daily_budget: Option<f64>,
+    /// Stop making LLM calls once this many USD have been spent this month.
+    pub monthly_budget: Option<f64>,
+    /// Prices by model name.
+    pub prices: std::collections::BTreeMap<String, Prices>,
+    /// Prices for models that aren't in `prices`.
+    pub default_prices: Prices,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            ledger: "usage.jsonl".into(),
+            daily_budget: None,
+            monthly_budget: None,
+            prices: Default::default(),
+            default_prices: Prices {
+                input: 3.0,
+                output: 15.0,
+                cache_write: 3.75,
+                cache_read: 0.3,
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct LedgerEntry {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub call_site: CallSite,
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub period: &'static str,
+    pub spent: f64,
+    pub budget: f64,
+}
+
+pub struct Ledger {
+    config: UsageConfig,
+    // Serializes appends from concurrent calls in this process.
+    lock: tokio::sync::Mutex<()>,
+    spend: tokio::sync::Mutex<Spend>,
+}
+
+/// Spend by day, as of the first `read_to` bytes of the ledger. Other
+/// processes append to it too, so it's caught up before each budget check.
+#[derive(Default)]
+struct Spend {
+    read_to: u64,
+    by_day: std::collections::BTreeMap<chrono::NaiveDate, f64>,
+}
+
+impl Ledger {
+    pub fn new(config: UsageConfig) -> Self {
+        Ledger {
+            config,
+            lock: tokio::sync::Mutex::new(()),
+            spend: Default::default(),
+        }
+    }
+
+    /// Parses one line of the ledger. A bad line (e.g. left by a write that
+    /// was cut short) is skipped, so it can't disable every LLM call.
+    fn parse_line(&self, line: &[u8]) -> Option<LedgerEntry> {
+        if line.trim_ascii().is_empty() {
+            return None;
+        }
+        match serde_json::from_slice(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Skipping bad line in {}: {e}", self.config.ledger.display());
+                None
+            }
+        }
+    }
+
+    pub async fn record(
+        &self,
+        call_site: CallSite,
+        model: &str,
+        usage: Usage,
+    ) -> anyhow::Result<()> {
+        let prices = self
+            .config
+            .prices
+            .get(model)
+            .unwrap_or(&self.config.default_prices);
+        let entry = LedgerEntry {
+            at: chrono::Utc::now(),
+            call_site,
+            model: model.to_string(),
+            usage,
+            cost_usd: prices.cost(&usage),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+
+        use tokio::io::AsyncWriteExt;
+        let _guard = self.lock.lock().await;
+        let mut f = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.ledger)
+            .await
+            .with_context(|| format!("Error opening {}", self.config.ledger.display()))?;
+        f.write_all(&line).await?;
+        f.flush().await?;
+        Ok(())
+    }
+
+    pub async fn entries(&self) -> anyhow::Result<Vec<LedgerEntry>> {
+        let contents = match tokio::fs::read_to_string(&self.config.ledger).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(contents
+            .lines()
+            .filter_map(|line| self.parse_line(line.as_bytes()))
+            .collect())
+    }
+
+    /// Reads whatever has been appended to the ledger since the last call,
+    /// up to the last complete line, and returns the spend by day.
+    async fn spend_by_day(
+        &self,
+    ) -> anyhow::Result<std::collections::BTreeMap<chrono::NaiveDate, f64>> {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+        let mut spend = self.spend.lock().await;
+        let mut f = match tokio::fs::File::open(&self.config.ledger).await {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                *spend = Spend::default();
+                return Ok(spend.by_day.clone());
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Error opening {}", self.config.ledger.display()));
+            }
+        };
+        // Start over if the ledger was replaced with a shorter one.
+        if f.metadata().await?.len() < spend.read_to {
+            *spend = Spend::default();
+        }
+        f.seek(std::io::SeekFrom::Start(spend.read_to)).await?;
+        let mut appended = Vec::new();
+        f.read_to_end(&mut appended)
+            .await
+            .with_context(|| format!("Error reading {}", self.config.ledger.display()))?;
+
+        // A line without its newline may still be being written.
+        let complete = appended
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        for line in appended[..complete].split(|&b| b == b'\n') {
+            if let Some(entry) = self.parse_line(line) {
+                let date = entry.at.with_timezone(&chrono::Local).date_naive();
+                *spend.by_day.entry(date).or_default() += entry.cost_usd;
+            }
+        }
+        spend.read_to += complete as u64;
+        Ok(spend.by_day.clone())
+    }
+
+    /// Whether today's or this month's spend has reached its budget.
+    pub async fn check_budget(&self) -> anyhow::Result<Option<BudgetExceeded>> {
+        if self.config.daily_budget.is_none() && self.config.monthly_budget.is_none() {
+            return Ok(None);
+        }
+        let today = chrono::Local::now().date_naive();
+        let mut spent_today = 0.0;
+        let mut spent_this_month = 0.0;
+        for (date, cost) in self.spend_by_day().await? {
+            if date == today {
+                spent_today += cost;
+            }
+            if (date.year(), date.month()) == (today.year(), today.month()) {
+                spent_this_month += cost;
+            }
+        }
+        for (period, spent, budget) in [
+            ("daily", spent_today, self.config.daily_budget),
+            ("monthly", spent_this_month, self.config.monthly_budget),
+        ] {
+            if let Some(budget) = budget
+                && spent >= budget
+            {
+                return Ok(Some(BudgetExceeded {
+                    period,
+                    spent,
+                    budget,
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// A plain-text summary of spend by day and by month, most recent first.
+    pub async fn report(&self, days: usize) -> anyhow::Result<String> {
+        #[derive(Default)]
+        struct Totals {
+            calls: u64,
+            usage: Usage,
+            cost: f64,
+        }
+
+        let mut by_day: std::collections::BTreeMap<(String, CallSite), Totals> = Default::default();
+        let mut by_month: std::collections::BTreeMap<String, Totals> = Default::default();
+        for entry in self.entries().await? {
+            let at = entry.at.with_timezone(&chrono::Local);
+            for totals in [
+                by_day
+                    .entry((at.format("%Y-%m-%d").to_string(), entry.call_site))
+                    .or_default(),
+                by_month.entry(at.format("%Y-%m").to_string()).or_default(),
+            ] {
+                totals.calls += 1;
+                totals.usage += entry.usage;
+                totals.cost += entry.cost_usd;
+            }
+        }
+
+        use std::fmt::Write;
+        let mut out = String::new();
+        let header = |out: &mut String, first: &str| {
+            writeln!(
+                out,
+                "{first:<18} {:>6} {:>10} {:>10} {:>10} {:>10} {:>9}",
+                "calls", "input", "output", "cache w", "cache r", "cost"
+            )
+        };
+        let row = |out: &mut String, label: &str, t: &Totals| {
+            writeln!(
+                out,
+                "{label:<18} {:>6} {:>10} {:>10} {:>10} {:>10} {:>9}",
+                t.calls,
+                t.usage.input_tokens,
+                t.usage.output_tokens,
+                t.usage.cache_creation_input_tokens,
+                t.usage.cache_read_input_tokens,
+                format!("${:.4}", t.cost),
+            )
+        };
+
+        header(&mut out, "Month")?;
+        for (month, totals) in by_month.iter().rev() {
+            row(&mut out, month, totals)?;
+        }
+        writeln!(out)?;
+        header(&mut out, "Day")?;
+        let all_days: std::collections::BTreeSet<_> = by_day.keys().map(|(day, _)| day).collect();
+        let recent_days: std::collections::BTreeSet<_> =
+            all_days.into_iter().rev().take(days).collect();
+        for ((day, call_site), totals) in by_day.iter().rev() {
+            if recent_days.contains(day) {
+                row(
+                    &mut out,
+                    &format!("{day} {}", format!("{call_site:?}").to_lowercase()),
+                    totals,
+                )?;
+            }
+        }
+
+        for (period, budget) in [
+            ("Daily", self.config.daily_budget),
+            ("Monthly", self.config.monthly_budget),
+        ] {
+            if let Some(budget) = budget {
+                writeln!(out, "\n{period} budget: ${budget:.2}")?;
+            }
+        }
+        if let Some(exceeded) = self.check_budget().await? {
+            writeln!(
+                out,
+                "LLM features are disabled: ${:.4} spent against a {} budget of ${:.2}",
+                exceeded.spent, exceeded.period, exceeded.budget
+            )?;
+        }
+        Ok(out)
+    }
+}
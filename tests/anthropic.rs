@@ -0,0 +1,330 @@
+//! Tests for the LLM calls against `adb::mock_anthropic`.
+
+mod common;
+
+use adb::anthropic::AnthropicError;
+use adb::mock_anthropic::{MockResponse, MockServer};
+
+fn user_text(text: &str) -> adb::anthropic::Conversation<'_> {
+    adb::anthropic::Conversation::new().user([adb::anthropic::MessageContent::Text {
+        text,
+        cache_control: None,
+    }])
+}
+
+#[tokio::test]
+async fn complete_sends_conversation() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("Hello!"));
+
+    let options = adb::anthropic::CompletionOptions::new()
+        .model("test-model")
+        .max_tokens(100);
+    let text = adb::anthropic::complete(
+        &server.client(),
+        &options,
+        &user_text("Hi").system("Be brief."),
+    )
+    .await
+    .unwrap();
+    assert_eq!(text, "Hello!");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["model"], "test-model");
+    assert_eq!(requests[0]["max_tokens"], 100);
+    assert_eq!(requests[0]["system"][0]["text"], "Be brief.");
+    assert_eq!(requests[0]["messages"][0]["role"], "user");
+    assert_eq!(requests[0]["messages"][0]["content"][0]["text"], "Hi");
+}
+
+#[tokio::test]
+async fn stream_completion_collects_text() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("A streamed fact."));
+
+    let text = adb::anthropic::stream_completion(
+        &server.client(),
+        &Default::default(),
+        &user_text("Tell me a fact"),
+    )
+    .await
+    .unwrap()
+    .text()
+    .await
+    .unwrap();
+    assert_eq!(text, "A streamed fact.");
+    assert_eq!(server.requests()[0]["stream"], true);
+}
+
+#[tokio::test]
+async fn gram_description() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("A cat wearing a hat."));
+
+    let png = b"not really a png";
+    let description = adb::gram::describe(
+        &server.client(),
+        &Default::default(),
+        png,
+        adb::anthropic::ImageMediaType::Png,
+    )
+    .await
+    .unwrap();
+    assert_eq!(description, "A cat wearing a hat.");
+
+    let content = &server.requests()[0]["messages"][0]["content"];
+    assert_eq!(content[1]["type"], "image");
+    assert_eq!(content[1]["source"]["media_type"], "image/png");
+    assert_eq!(content[1]["source"]["data"], "bm90IHJlYWxseSBhIHBuZw==");
+}
+
+#[tokio::test]
+async fn documents_and_image_urls() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("Both read."));
+
+    let conversation = adb::anthropic::Conversation::new().user([
+        adb::anthropic::MessageContent::Document {
+            source: adb::anthropic::DocumentSource::pdf_base64("JVBERi0x"),
+            title: Some("Order"),
+            cache_control: None,
+        },
+        adb::anthropic::MessageContent::Image {
+            source: adb::anthropic::ImageSource::new_url("https://example.com/photo.webp"),
+            cache_control: None,
+        },
+    ]);
+    adb::anthropic::complete(&server.client(), &Default::default(), &conversation)
+        .await
+        .unwrap();
+
+    let content = &server.requests()[0]["messages"][0]["content"];
+    assert_eq!(
+        content[0],
+        serde_json::json!({
+            "type": "document",
+            "source": {
+                "type": "base64",
+                "media_type": "application/pdf",
+                "data": "JVBERi0x",
+            },
+            "title": "Order",
+        })
+    );
+    assert_eq!(
+        content[1],
+        serde_json::json!({
+            "type": "image",
+            "source": { "type": "url", "url": "https://example.com/photo.webp" },
+        })
+    );
+}
+
+#[test]
+fn image_media_types() {
+    use adb::anthropic::ImageMediaType;
+
+    assert_eq!(
+        ImageMediaType::from_mime_type("image/JPEG; q=0.9").unwrap(),
+        ImageMediaType::Jpeg
+    );
+    assert_eq!(
+        ImageMediaType::from_format(image::ImageFormat::WebP).unwrap(),
+        ImageMediaType::Webp
+    );
+    assert!(matches!(
+        ImageMediaType::from_mime_type("image/heic"),
+        Err(AnthropicError::UnsupportedMediaType { .. })
+    ));
+    assert!(ImageMediaType::from_format(image::ImageFormat::Bmp).is_err());
+}
+
+#[tokio::test]
+async fn classifier_decisions() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::tool_use(
+        "classify_filings",
+        serde_json::json!({
+            "classifications": [
+                {
+                    "filing": 2,
+                    "substantive": false,
+                    "category": "notice",
+                    "reason": "A notice of appearance.",
+                },
+                {
+                    "filing": 1,
+                    "substantive": true,
+                    "category": "order",
+                    "reason": "Grants a preliminary injunction.",
+                },
+            ],
+        }),
+    ));
+
+    let entries: Vec<adb::courtlistener::DocketEntry> = serde_json::from_str(
+        r#"[
+            {"description": "ORDER granting 12 Motion for Preliminary Injunction.", "entry_number": 34},
+            {"description": "NOTICE of Appearance by Jane Doe.", "entry_number": 35},
+            {"description": "Something the model forgot about.", "entry_number": 36}
+        ]"#,
+    )
+    .unwrap();
+    let classifications = adb::courtlistener::classify_entries(
+        &server.client(),
+        &adb::config::Completions::default().docket_classifier,
+        &entries,
+    )
+    .await;
+
+    assert_eq!(classifications.len(), 3);
+    assert!(classifications[0].substantive);
+    assert_eq!(classifications[0].category, "order");
+    assert!(!classifications[1].substantive);
+    assert_eq!(classifications[1].category, "notice");
+    // Entries without a verdict are printed rather than dropped.
+    assert!(classifications[2].substantive);
+    assert_eq!(classifications[2].category, "unclassified");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0]["tool_choice"]["name"], "classify_filings");
+    assert_eq!(
+        requests[0]["system"][0]["cache_control"]["type"],
+        "ephemeral"
+    );
+    let filings = requests[0]["messages"][0]["content"][0]["text"]
+        .as_str()
+        .unwrap();
+    assert!(filings.contains("Filing 3:"));
+}
+
+#[tokio::test]
+async fn classifier_errors_leave_entries_unclassified() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(
+        400,
+        "invalid_request_error",
+        "prompt is too long",
+    ));
+
+    let entries: Vec<adb::courtlistener::DocketEntry> =
+        serde_json::from_str(r#"[{"description": "ORDER granting motion.", "entry_number": 34}]"#)
+            .unwrap();
+    let classifications = adb::courtlistener::classify_entries(
+        &server.client(),
+        &adb::config::Completions::default().docket_classifier,
+        &entries,
+    )
+    .await;
+
+    assert_eq!(classifications.len(), 1);
+    assert!(classifications[0].substantive);
+    assert_eq!(classifications[0].category, "unclassified");
+    assert!(classifications[0].reason.contains("prompt is too long"));
+}
+
+#[tokio::test]
+async fn retries_rate_limits() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(429, "rate_limit_error", "Slow down"));
+    server.push(MockResponse::error(529, "overloaded_error", "Overloaded"));
+    server.push(MockResponse::text("Finally."));
+
+    let text = adb::anthropic::complete(&server.client(), &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap();
+    assert_eq!(text, "Finally.");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn does_not_retry_bad_requests() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(
+        400,
+        "invalid_request_error",
+        "max_tokens: too large",
+    ));
+
+    let err = adb::anthropic::complete(&server.client(), &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AnthropicError::Api { status, error_type, .. }
+            if status.as_u16() == 400 && error_type == "invalid_request_error"),
+        "{err:?}"
+    );
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn authentication_error() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(
+        401,
+        "authentication_error",
+        "invalid x-api-key",
+    ));
+
+    let err = adb::anthropic::complete(&server.client(), &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, AnthropicError::Authentication { .. }),
+        "{err:?}"
+    );
+}
+
+#[tokio::test]
+async fn refusal() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::Refusal);
+    server.push(MockResponse::Refusal);
+
+    let err = adb::anthropic::complete(&server.client(), &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnthropicError::Refusal), "{err:?}");
+
+    let err =
+        adb::anthropic::stream_completion(&server.client(), &Default::default(), &user_text("Hi"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap_err();
+    assert!(matches!(err, AnthropicError::Refusal), "{err:?}");
+}
+
+#[tokio::test]
+async fn usage_is_recorded_and_budget_enforced() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("One."));
+    server.push(MockResponse::text("Two."));
+
+    let ledger = common::test_ledger(&common::temp_path("budget.jsonl"), Some(0.0005));
+    let client = server
+        .client()
+        .ledger(ledger.clone())
+        .call_site(adb::usage::CallSite::Gram);
+
+    adb::anthropic::complete(&client, &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap();
+    let entries = ledger.entries().await.unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].call_site, adb::usage::CallSite::Gram);
+    assert_eq!(
+        entries[0].usage.input_tokens,
+        adb::mock_anthropic::USAGE.input_tokens
+    );
+
+    // The first call spent more than the budget, so the second isn't sent.
+    let err = adb::anthropic::complete(&client, &Default::default(), &user_text("Hi"))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnthropicError::BudgetExceeded(_)), "{err:?}");
+    assert_eq!(server.requests().len(), 1);
+}
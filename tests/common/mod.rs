@@ -0,0 +1,23 @@
+//! Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+/// A path under the system temp dir for this run of the tests, with
+/// whatever an earlier run left there removed.
+pub fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("adb-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// A ledger kept at `path`, e.g. one from [temp_path].
+pub fn test_ledger(
+    path: &std::path::Path,
+    daily_budget: Option<f64>,
+) -> std::sync::Arc<adb::usage::Ledger> {
+    std::sync::Arc::new(adb::usage::Ledger::new(adb::usage::UsageConfig {
+        ledger: path.to_path_buf(),
+        daily_budget,
+        ..Default::default()
+    }))
+}
@@ -0,0 +1,351 @@
+//! Tests for CourtListener webhook handling.
+
+mod common;
+
+use adb::courtlistener::{EventType, WebhookConfig, WebhookEvent, WebhookGuard, WebhookRejection};
+use adb::delivery_log::{DeliveryLog, Record};
+
+const PEER: std::net::IpAddr = std::net::IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+const BODY: &[u8] = br#"{"webhook": {"version": 2}, "payload": {"results": []}}"#;
+
+#[test]
+fn accepts_deliveries() {
+    let guard = WebhookGuard::new(Default::default());
+    let delivery = guard.check(PEER, None, Some("key-1"), BODY).unwrap();
+    assert_eq!(delivery.idempotency_key, "key-1");
+}
+
+#[test]
+fn full_payload() {
+    let guard = WebhookGuard::new(Default::default());
+    let body = br#"{
+        "webhook": {"version": 2, "event_type": 1, "date_created": "2025-03-13T18:22:01Z"},
+        "payload": {"results": [{
+            "id": 401522781,
+            "docket": 67495437,
+            "case_name": "Doe v. Roe",
+            "court_id": "dcd",
+            "docket_number": "1:25-cv-00123",
+            "description": "ORDER granting 12 Motion for Preliminary Injunction.",
+            "entry_number": 34,
+            "date_filed": "2025-03-13",
+            "recap_documents": [{
+                "description": "Order",
+                "document_number": "34",
+                "page_count": 12,
+                "absolute_url": "/docket/67495437/34/doe-v-roe/",
+                "filepath_local": "recap/gov.uscourts.dcd.67495437.34.0.pdf"
+            }]
+        }]}
+    }"#;
+    let webhook = guard
+        .check(PEER, None, Some("key-1"), body)
+        .unwrap()
+        .webhook;
+    assert_eq!(webhook.webhook.event_type, EventType::DocketAlert);
+    let WebhookEvent::DocketAlert(payload) = webhook.event else {
+        panic!("expected a docket alert, got {:?}", webhook.event);
+    };
+    let entry = &payload.results[0];
+    assert_eq!(entry.key().as_deref(), Some("entry:401522781"));
+    assert_eq!(entry.case_name.as_deref(), Some("Doe v. Roe"));
+    assert_eq!(entry.court.as_deref(), Some("dcd"));
+    assert_eq!(entry.docket_number.as_deref(), Some("1:25-cv-00123"));
+    let document = &entry.recap_documents.as_ref().unwrap()[0];
+    assert_eq!(document.page_count, Some(12));
+    assert_eq!(
+        document.filepath_local.as_deref(),
+        Some("recap/gov.uscourts.dcd.67495437.34.0.pdf")
+    );
+}
+
+#[test]
+fn event_types() {
+    let guard = WebhookGuard::new(Default::default());
+    let event = |body: &[u8]| {
+        guard
+            .check(PEER, None, Some("key"), body)
+            .map(|d| d.webhook.event)
+    };
+
+    assert!(matches!(
+        event(
+            br#"{
+                "webhook": {"version": 2, "event_type": 2},
+                "payload": {
+                    "alert": {"name": "Net neutrality", "query": "q=net+neutrality"},
+                    "results": [{"caseName": "Mozilla v. FCC", "docketNumber": "18-1051"}]
+                }
+            }"#
+        ),
+        Ok(WebhookEvent::SearchAlert(alert))
+            if alert.results[0].case_name.as_deref() == Some("Mozilla v. FCC")
+    ));
+    assert!(matches!(
+        event(
+            br#"{
+                "webhook": {"version": 2, "event_type": 3},
+                "payload": {"id": 7, "status": 2, "court": "dcd", "docket": 67495437}
+            }"#
+        ),
+        Ok(WebhookEvent::RecapFetch(fetch)) if fetch.status == Some(2)
+    ));
+    assert!(matches!(
+        event(
+            br#"{
+                "webhook": {"version": 2, "event_type": 4},
+                "payload": {"disabled_alerts": [{"docket": 1, "date_last_hit": null}]}
+            }"#
+        ),
+        Ok(WebhookEvent::OldDocketAlertsReport(report))
+            if report.disabled_alerts.len() == 1 && report.old_alerts.is_empty()
+    ));
+    // Accepted, so CourtListener doesn't retry it.
+    assert!(matches!(
+        event(br#"{"webhook": {"version": 2, "event_type": 99}, "payload": {"new": []}}"#),
+        Ok(WebhookEvent::Unknown(99))
+    ));
+    assert!(matches!(
+        event(br#"{"webhook": {"version": 2, "event_type": "test"}}"#),
+        Err(WebhookRejection::Malformed(_))
+    ));
+    // The payload has to match the event type.
+    assert!(matches!(
+        event(br#"{"webhook": {"version": 2, "event_type": 2}, "payload": {"results": []}}"#),
+        Err(WebhookRejection::Malformed(_))
+    ));
+}
+
+#[test]
+fn rejects_bad_deliveries() {
+    let guard = WebhookGuard::new(Default::default());
+    assert!(matches!(
+        guard.check(PEER, None, None, BODY),
+        Err(WebhookRejection::MissingIdempotencyKey)
+    ));
+    assert!(matches!(
+        guard.check(PEER, None, Some("has spaces"), BODY),
+        Err(WebhookRejection::InvalidIdempotencyKey)
+    ));
+    assert!(matches!(
+        guard.check(PEER, None, Some("key"), b"{\"payload\": {}}"),
+        Err(WebhookRejection::Malformed(_))
+    ));
+    assert!(matches!(
+        guard.check(
+            PEER,
+            None,
+            Some("key"),
+            br#"{"webhook": {"version": 99}, "payload": {"results": []}}"#
+        ),
+        Err(WebhookRejection::UnsupportedVersion(99))
+    ));
+}
+
+#[test]
+fn ip_allowlist() {
+    let allowed: std::net::IpAddr = "203.0.113.7".parse().unwrap();
+    let guard = WebhookGuard::new(WebhookConfig {
+        allowed_ips: vec![allowed],
+        trust_forwarded_for: false,
+        ..Default::default()
+    });
+    assert!(guard.check(allowed, None, Some("key-1"), BODY).is_ok());
+    // The header is ignored unless there's a proxy to vouch for it.
+    assert!(matches!(
+        guard.check(PEER, Some("203.0.113.7"), Some("key-2"), BODY),
+        Err(WebhookRejection::ForbiddenSource(ip)) if ip == PEER
+    ));
+
+    let guard = WebhookGuard::new(WebhookConfig {
+        allowed_ips: vec![allowed],
+        trust_forwarded_for: true,
+        ..Default::default()
+    });
+    assert!(
+        guard
+            .check(PEER, Some("198.51.100.1, 203.0.113.7"), Some("key-3"), BODY)
+            .is_ok()
+    );
+    assert!(matches!(
+        guard.check(PEER, Some("203.0.113.7, 198.51.100.1"), Some("key-4"), BODY),
+        Err(WebhookRejection::ForbiddenSource(_))
+    ));
+}
+
+const DOCKET_ALERT: &[u8] = br#"{
+    "webhook": {"version": 2, "event_type": 1},
+    "payload": {"results": [{
+        "id": 1,
+        "case_name": "Doe v. Roe",
+        "description": "ORDER granting motion.",
+        "entry_number": 34
+    }]}
+}"#;
+
+async fn wait_until<F: std::future::Future<Output = bool>>(mut done: impl FnMut() -> F) {
+    for _ in 0..1000 {
+        if done().await {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("Timed out");
+}
+
+#[tokio::test]
+async fn prints_docket_alerts_without_a_client() {
+    let dir = common::temp_path("webhook-queue");
+    let log_path = common::temp_path("webhook-deliveries.jsonl");
+    let sink = std::sync::Arc::new(adb::printer::MemorySink::default());
+    let printer = adb::printer::Printer::new(Default::default(), sink.clone());
+    let (queue, actor) = adb::queue::PrintQueue::open("court", &dir, printer).unwrap();
+    tokio::spawn(actor.run());
+    let log = std::sync::Arc::new(DeliveryLog::open(&log_path).unwrap());
+
+    let delivery = WebhookGuard::new(Default::default())
+        .check(PEER, None, Some("key-1"), DOCKET_ALERT)
+        .unwrap();
+    let new = adb::courtlistener::handle_webhook(
+        None,
+        Default::default(),
+        vec![queue],
+        log.clone(),
+        delivery,
+    )
+    .await
+    .unwrap();
+    assert!(new);
+
+    wait_until(|| async { !sink.jobs().is_empty() }).await;
+    let job = String::from_utf8_lossy(&sink.jobs()[0]).into_owned();
+    assert!(job.contains("Doe v. Roe"), "{job}");
+    assert!(job.contains("unclassified"), "{job}");
+    // Recorded once it's queued, so a retry is ignored.
+    wait_until(|| async {
+        adb::delivery_log::read(&log_path)
+            .unwrap()
+            .iter()
+            .any(|record| matches!(record, Record::Delivery { .. }))
+    })
+    .await;
+    assert!(!log.start_delivery("key-1").await);
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&log_path);
+}
+
+#[tokio::test]
+async fn retries_deliveries_that_failed() {
+    let dir = common::temp_path("webhook-failing-queue");
+    let log_path = common::temp_path("webhook-failing-deliveries.jsonl");
+    let printer = adb::printer::Printer::new(
+        Default::default(),
+        std::sync::Arc::new(adb::printer::MemorySink::default()),
+    );
+    let (queue, _actor) = adb::queue::PrintQueue::open("court", &dir, printer).unwrap();
+    // Nowhere to save jobs, so queueing the alert fails.
+    std::fs::remove_dir_all(&dir).unwrap();
+    let log = std::sync::Arc::new(DeliveryLog::open(&log_path).unwrap());
+
+    let delivery = WebhookGuard::new(Default::default())
+        .check(PEER, None, Some("key-1"), DOCKET_ALERT)
+        .unwrap();
+    let new = adb::courtlistener::handle_webhook(
+        None,
+        Default::default(),
+        vec![queue],
+        log.clone(),
+        delivery,
+    )
+    .await
+    .unwrap();
+    assert!(new);
+
+    // Released once processing fails, so a retry is taken.
+    wait_until(|| log.start_delivery("key-1")).await;
+    let records = adb::delivery_log::read(&log_path).unwrap();
+    assert!(
+        !records
+            .iter()
+            .any(|record| matches!(record, Record::Delivery { .. }))
+    );
+    let _ = std::fs::remove_file(&log_path);
+}
+
+fn entry(key: &str, substantive: bool, printed: bool) -> Record {
+    Record::Entry {
+        at: chrono::Utc::now(),
+        idempotency_key: "key-1".to_string(),
+        entry: Some(key.to_string()),
+        entry_number: Some(1),
+        description: Some("ORDER granting motion.".to_string()),
+        substantive,
+        category: "order".to_string(),
+        printed,
+    }
+}
+
+#[tokio::test]
+async fn delivery_log_survives_restarts() {
+    let path = common::temp_path("deliveries.jsonl");
+
+    let log = DeliveryLog::open(&path).unwrap();
+    assert!(log.start_delivery("key-1").await);
+    // Still being processed.
+    assert!(!log.start_delivery("key-1").await);
+    log.release_delivery("key-1").await;
+    assert!(log.start_delivery("key-1").await);
+    log.record_delivery("key-1", 3).await.unwrap();
+    assert!(!log.start_delivery("key-1").await);
+    log.record_entry(entry("entry:1", true, true))
+        .await
+        .unwrap();
+    log.record_entry(entry("entry:2", false, false))
+        .await
+        .unwrap();
+    log.record_entry(entry("entry:3", true, false))
+        .await
+        .unwrap();
+
+    let log = DeliveryLog::open(&path).unwrap();
+    assert!(!log.start_delivery("key-1").await);
+    assert!(log.seen_entry("entry:1").await);
+    assert!(log.seen_entry("entry:2").await);
+    // It should have printed but didn't, so it gets another go.
+    assert!(!log.seen_entry("entry:3").await);
+
+    let records = adb::delivery_log::read(&path).unwrap();
+    assert_eq!(records.len(), 4);
+    let history = adb::delivery_log::history(&records, 10).unwrap();
+    assert!(history.contains("failed"));
+    assert!(history.contains("delivery key-1 with 3 entries"));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn delivery_log_skips_bad_lines() {
+    let path = common::temp_path("bad-deliveries.jsonl");
+    let delivery = serde_json::to_string(&Record::Delivery {
+        at: chrono::Utc::now(),
+        idempotency_key: "key-1".to_string(),
+        entries: 1,
+    })
+    .unwrap();
+    std::fs::write(
+        &path,
+        format!("{delivery}\nnot json\n{{\"type\": \"delivery\", \"at\": \"2025-"),
+    )
+    .unwrap();
+
+    let log = DeliveryLog::open(&path).unwrap();
+    assert!(!log.start_delivery("key-1").await);
+    assert!(log.start_delivery("key-2").await);
+    log.record_delivery("key-2", 1).await.unwrap();
+
+    // The unfinished line was dropped, so the new record stands on its own.
+    let records = adb::delivery_log::read(&path).unwrap();
+    assert_eq!(records.len(), 2);
+    let log = DeliveryLog::open(&path).unwrap();
+    assert!(!log.start_delivery("key-2").await);
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,322 @@
+//! Golden-file tests for the bytes each layout sends to the printer.
+//!
+//! Run with `UPDATE_GOLDEN=1` to regenerate the files in `tests/golden/`
+//! after an intentional layout change.
+
+use chrono::TimeZone;
+
+fn check_golden(name: &str, actual: &[u8]) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+    }
+    let expected =
+        std::fs::read(&path).unwrap_or_else(|e| panic!("Error reading {}: {e}", path.display()));
+    assert!(
+        expected == actual,
+        "output does not match {}; rerun with UPDATE_GOLDEN=1 if this is intentional",
+        path.display()
+    );
+}
+
+fn test_printer() -> (
+    adb::printer::Printer,
+    std::sync::Arc<adb::printer::MemorySink>,
+) {
+    let sink = std::sync::Arc::new(adb::printer::MemorySink::default());
+    let printer = adb::printer::Printer::new(Default::default(), sink.clone());
+    (printer, sink)
+}
+
+fn test_time() -> chrono::DateTime<chrono::Local> {
+    chrono::Local
+        .with_ymd_and_hms(2025, 3, 14, 9, 26, 53)
+        .unwrap()
+}
+
+fn test_brief() -> adb::brief::Brief {
+    adb::brief::Brief {
+        date: test_time(),
+        weather: vec![
+            ("Today".to_string(), "61°F, Sunny".to_string()),
+            ("Saturday".to_string(), "55°F, Chance Rain".to_string()),
+        ],
+        todo_items: vec![
+            adb::todoist::TodoItem {
+                content: "Call the bank".to_string(),
+                time: chrono::NaiveTime::from_hms_opt(14, 30, 0),
+                url: Some("https://app.todoist.com/app/task/6X7rM8997g3RQmvh".to_string()),
+            },
+            adb::todoist::TodoItem {
+                content: "Water the plants".to_string(),
+                time: None,
+                url: None,
+            },
+        ],
+        us_history_fact: Some(
+            "On March 14, 1794, Eli Whitney received a patent for the cotton gin.".to_string(),
+        ),
+    }
+}
+
+#[tokio::test]
+async fn brief() {
+    let (printer, sink) = test_printer();
+    printer
+        .print_receipt(&adb::brief::brief_receipt(&test_brief()))
+        .await
+        .unwrap();
+
+    let jobs = sink.jobs();
+    assert_eq!(jobs.len(), 1);
+    check_golden("brief.bin", &jobs[0]);
+}
+
+#[tokio::test]
+async fn brief_streamed() {
+    // Printed a few characters at a time, the brief looks the same as when
+    // it's printed all at once.
+    let (printer, sink) = test_printer();
+    let brief = adb::brief::Brief {
+        us_history_fact: None,
+        ..test_brief()
+    };
+    let fact = test_brief().us_history_fact.unwrap();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    for chunk in fact.as_bytes().chunks(5) {
+        tx.send(String::from_utf8(chunk.to_vec()).unwrap()).unwrap();
+    }
+    drop(tx);
+    adb::brief::print_streaming(std::slice::from_ref(&printer), &brief, rx)
+        .await
+        .unwrap();
+
+    let jobs = sink.jobs();
+    assert!(jobs.len() > 2);
+    let streamed = adb::preview::render(&jobs.concat(), 576).unwrap();
+    let expected = std::fs::read(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/brief.bin"),
+    )
+    .unwrap();
+    assert_eq!(streamed, adb::preview::render(&expected, 576).unwrap());
+}
+
+#[tokio::test]
+async fn brief_streamed_without_fact() {
+    let (printer, sink) = test_printer();
+    let brief = adb::brief::Brief {
+        us_history_fact: None,
+        ..test_brief()
+    };
+    let (_, rx) = tokio::sync::mpsc::unbounded_channel();
+    adb::brief::print_streaming(std::slice::from_ref(&printer), &brief, rx)
+        .await
+        .unwrap();
+
+    let expected = printer
+        .render(&adb::brief::brief_receipt(&brief))
+        .await
+        .unwrap();
+    let streamed = adb::preview::render(&sink.jobs().concat(), 576).unwrap();
+    assert_eq!(streamed, adb::preview::render(&expected, 576).unwrap());
+}
+
+#[tokio::test]
+async fn gram() {
+    let (printer, sink) = test_printer();
+    let gram = adb::gram::Gram {
+        received_at: test_time(),
+        peer_ip: Some("203.0.113.7".to_string()),
+        user_name: Some("alex".to_string()),
+        image: image::GrayImage::from_fn(16, 8, |x, y| image::Luma([((x + y) % 2 * 255) as u8])),
+        description: Some("A small checkerboard.".to_string()),
+    };
+    printer
+        .print_receipt(&adb::gram::gram_receipt(&gram))
+        .await
+        .unwrap();
+
+    let jobs = sink.jobs();
+    assert_eq!(jobs.len(), 1);
+    check_golden("gram.bin", &jobs[0]);
+}
+
+#[tokio::test]
+async fn startup_message() {
+    let (printer, _) = test_printer();
+    let job = printer
+        .render(&adb::gram::startup_receipt(test_time()))
+        .await
+        .unwrap();
+    check_golden("startup.bin", &job);
+}
+
+#[tokio::test]
+async fn docket_alerts() {
+    let (printer, _) = test_printer();
+    let entries: Vec<adb::courtlistener::DocketEntry> = serde_json::from_str(
+        r#"[
+            {
+                "id": 401522781,
+                "docket": 67495437,
+                "case_name": "Doe v. Roe",
+                "court": "dcd",
+                "docket_number": "1:25-cv-00123",
+                "description": "ORDER granting 12 Motion for Preliminary Injunction.",
+                "entry_number": 34,
+                "date_filed": "2025-03-13",
+                "recap_documents": [
+                    {
+                        "description": "Order",
+                        "document_number": "34",
+                        "page_count": 12,
+                        "absolute_url": "/docket/67495437/34/doe-v-roe/"
+                    },
+                    {"description": "", "document_number": "34"}
+                ]
+            },
+            {
+                "docket": 68011209,
+                "case_name": "Smith v. Jones",
+                "court_id": "cadc",
+                "docket_number": "25-5021",
+                "description": "MEMORANDUM OPINION regarding 34 Order.",
+                "entry_number": 35,
+                "date_filed": "2025-03-13",
+                "recap_documents": [
+                    {
+                        "description": "Memorandum Opinion",
+                        "document_number": "35",
+                        "page_count": 1,
+                        "filepath_local": "recap/gov.uscourts.cadc.68011209.35.0.pdf"
+                    }
+                ]
+            }
+        ]"#,
+    )
+    .unwrap();
+    let classifications = [
+        adb::courtlistener::Classification {
+            substantive: true,
+            category: "order".to_string(),
+            reason: "Grants a preliminary injunction against the defendant.".to_string(),
+        },
+        adb::courtlistener::Classification {
+            substantive: true,
+            category: "opinion".to_string(),
+            reason: "Explains the reasoning behind the injunction.".to_string(),
+        },
+    ];
+    let alerts: Vec<_> = entries.into_iter().zip(classifications).collect();
+    let job = printer
+        .render(&adb::courtlistener::docket_alerts_receipt(
+            &alerts,
+            test_time(),
+        ))
+        .await
+        .unwrap();
+    check_golden("docket_alerts.bin", &job);
+}
+
+#[tokio::test]
+async fn search_alert() {
+    let (printer, _) = test_printer();
+    let alert: adb::courtlistener::SearchAlert = serde_json::from_str(
+        r#"{
+            "alert": {"name": "Net neutrality", "query": "q=\"net neutrality\""},
+            "results": [
+                {
+                    "caseName": "Mozilla Corp. v. FCC",
+                    "court": "Court of Appeals for the D.C. Circuit",
+                    "docketNumber": "18-1051",
+                    "dateFiled": "2019-10-01",
+                    "snippet": "the <mark>net neutrality</mark> rules were repealed",
+                    "absolute_url": "/opinion/4669862/mozilla-corp-v-fcc/"
+                },
+                {"caseName": "Ohio Telecom Ass'n v. FCC", "snippet": ""}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let job = printer
+        .render(&adb::courtlistener::search_alert_receipt(
+            &alert,
+            test_time(),
+        ))
+        .await
+        .unwrap();
+    check_golden("search_alert.bin", &job);
+}
+
+#[tokio::test]
+async fn recap_fetch() {
+    let (printer, _) = test_printer();
+    let fetch: adb::courtlistener::RecapFetch = serde_json::from_str(
+        r#"{
+            "id": 7,
+            "status": 3,
+            "message": "Unable to log into PACER.",
+            "court": "dcd",
+            "docket": 67495437,
+            "recap_document": null,
+            "date_completed": "2025-03-14T09:20:00-04:00"
+        }"#,
+    )
+    .unwrap();
+    let job = printer
+        .render(&adb::courtlistener::recap_fetch_receipt(
+            &fetch,
+            test_time(),
+        ))
+        .await
+        .unwrap();
+    check_golden("recap_fetch.bin", &job);
+}
+
+#[tokio::test]
+async fn old_docket_alerts() {
+    let (printer, _) = test_printer();
+    let report: adb::courtlistener::OldDocketAlertsReport = serde_json::from_str(
+        r#"{
+            "old_alerts": [{"docket": 67495437, "date_last_hit": "2024-09-02T14:01:00Z"}],
+            "disabled_alerts": [{"docket": 4214664, "date_last_hit": null}]
+        }"#,
+    )
+    .unwrap();
+    let job = printer
+        .render(&adb::courtlistener::old_docket_alerts_receipt(
+            &report,
+            test_time(),
+        ))
+        .await
+        .unwrap();
+    check_golden("old_docket_alerts.bin", &job);
+}
+
+#[tokio::test]
+async fn barcode() {
+    let (printer, _) = test_printer();
+    let receipt = adb::receipt::Receipt::new()
+        .centered("Job")
+        .barcode("0001a2b3c4d5e6f7")
+        .feed(3)
+        .cut();
+    let job = printer.render(&receipt).await.unwrap();
+    check_golden("barcode.bin", &job);
+
+    let bad = adb::receipt::Receipt::new().barcode("naïve");
+    assert!(printer.render(&bad).await.is_err());
+
+    // A literal `{` is doubled, and counted in the length.
+    let braces = adb::receipt::Receipt::new().barcode("a{b");
+    let job = printer.render(&braces).await.unwrap();
+    assert!(job.windows(10).any(|w| w == b"\x1dkI\x06{Ba{{b"));
+    let preview = adb::preview::render(&job, 576).unwrap();
+    let plain = adb::receipt::Receipt::new().barcode("a}b");
+    let plain = adb::preview::render(&printer.render(&plain).await.unwrap(), 576).unwrap();
+    assert_eq!(preview.dimensions(), plain.dimensions());
+    let too_long = adb::receipt::Receipt::new().barcode("{".repeat(127));
+    assert!(printer.render(&too_long).await.is_err());
+}
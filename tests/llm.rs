@@ -0,0 +1,192 @@
+//! Tests for LLM providers and fallback chains against
+//! `adb::mock_anthropic`.
+
+mod common;
+
+use adb::llm::LlmProvider;
+use adb::mock_anthropic::{MockResponse, MockServer};
+
+fn local_provider(
+    server: &MockServer,
+) -> std::collections::BTreeMap<String, adb::llm::ProviderConfig> {
+    [(
+        "local".to_string(),
+        adb::llm::ProviderConfig::OpenAi {
+            base_url: server.base_url(),
+            model: "local-model".to_string(),
+            api_key_env: None,
+        },
+    )]
+    .into()
+}
+
+#[tokio::test]
+async fn openai_provider_request() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("A dog on a skateboard."));
+
+    let provider = adb::llm::OpenAiProvider::new(
+        "local",
+        &local_provider(&server)["local"],
+        reqwest::Client::new(),
+    )
+    .unwrap();
+    let options = adb::anthropic::CompletionOptions::new().max_tokens(200);
+    let description = adb::gram::describe(
+        &provider,
+        &options,
+        b"png",
+        adb::anthropic::ImageMediaType::Png,
+    )
+    .await
+    .unwrap();
+    assert_eq!(description, "A dog on a skateboard.");
+
+    let request = &server.requests()[0];
+    assert_eq!(request["model"], "local-model");
+    assert_eq!(request["max_tokens"], 200);
+    assert_eq!(request["messages"][0]["role"], "user");
+    let content = &request["messages"][0]["content"];
+    assert_eq!(content[0]["type"], "text");
+    assert_eq!(content[1]["type"], "image_url");
+    assert_eq!(content[1]["image_url"]["url"], "data:image/png;base64,cG5n");
+}
+
+#[tokio::test]
+async fn falls_back_when_anthropic_fails() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(
+        401,
+        "authentication_error",
+        "invalid x-api-key",
+    ));
+    server.push(MockResponse::text("From the local model."));
+
+    let chain = adb::llm::FallbackChain::for_route(
+        &["anthropic".to_string(), "local".to_string()],
+        Some(&server.client()),
+        &local_provider(&server),
+        &reqwest::Client::new(),
+    )
+    .unwrap()
+    .unwrap();
+    let conversation =
+        adb::anthropic::Conversation::new().user([adb::anthropic::MessageContent::Text {
+            text: "Hi",
+            cache_control: None,
+        }]);
+    let text = chain
+        .complete(&Default::default(), &conversation)
+        .await
+        .unwrap();
+    assert_eq!(text, "From the local model.");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1]["model"], "local-model");
+}
+
+#[tokio::test]
+async fn keeps_budget_error_when_every_provider_fails() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::error(
+        400,
+        "invalid_request_error",
+        "model not loaded",
+    ));
+
+    let ledger = common::test_ledger(&common::temp_path("llm-budget.jsonl"), Some(0.0));
+    let chain = adb::llm::FallbackChain::for_route(
+        &["anthropic".to_string(), "local".to_string()],
+        Some(&server.client().ledger(ledger)),
+        &local_provider(&server),
+        &reqwest::Client::new(),
+    )
+    .unwrap()
+    .unwrap();
+    let conversation =
+        adb::anthropic::Conversation::new().user([adb::anthropic::MessageContent::Text {
+            text: "Hi",
+            cache_control: None,
+        }]);
+    let err = chain
+        .complete(&Default::default(), &conversation)
+        .await
+        .unwrap_err();
+    assert!(adb::llm::is_budget_exceeded(&err), "{err:#}");
+    // Only the local model was asked.
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(server.requests()[0]["model"], "local-model");
+}
+
+#[tokio::test]
+async fn streams_text_as_it_arrives() {
+    let server = MockServer::start().await.unwrap();
+    server.push(MockResponse::text("On this day in 1794, a patent."));
+
+    let chain = adb::llm::FallbackChain::for_route(
+        &["anthropic".to_string(), "local".to_string()],
+        Some(&server.client()),
+        &local_provider(&server),
+        &reqwest::Client::new(),
+    )
+    .unwrap()
+    .unwrap();
+    let conversation =
+        adb::anthropic::Conversation::new().user([adb::anthropic::MessageContent::Text {
+            text: "Hi",
+            cache_control: None,
+        }]);
+    let deltas = std::sync::Mutex::new(Vec::new());
+    let text = chain
+        .stream(&Default::default(), &conversation, &|delta| {
+            deltas.lock().unwrap().push(delta.to_string())
+        })
+        .await
+        .unwrap();
+    assert_eq!(text, "On this day in 1794, a patent.");
+    let deltas = deltas.into_inner().unwrap();
+    assert!(deltas.len() > 1);
+    assert_eq!(deltas.concat(), text);
+    assert_eq!(server.requests()[0]["stream"], true);
+}
+
+#[tokio::test]
+async fn anthropic_is_skipped_without_a_key() {
+    let server = MockServer::start().await.unwrap();
+    let route = ["anthropic".to_string()];
+    let chain = adb::llm::FallbackChain::for_route(
+        &route,
+        None,
+        &local_provider(&server),
+        &reqwest::Client::new(),
+    )
+    .unwrap();
+    assert!(chain.is_none());
+}
+
+#[test]
+fn llm_routes_must_name_known_providers() {
+    let config: adb::config::Config = toml::from_str(
+        r#"
+        [llm_providers.local]
+        type = "openai"
+        base_url = "http://localhost:11434"
+        model = "llama3.2-vision"
+
+        [llm_routes]
+        brief = ["anthropic", "local"]
+        gram = ["ollama"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config.llm_route(adb::config::JobKind::Brief).unwrap(),
+        ["anthropic", "local"]
+    );
+    assert!(config.llm_route(adb::config::JobKind::Gram).is_err());
+    assert_eq!(
+        config.llm_route(adb::config::JobKind::Court).unwrap(),
+        ["anthropic"]
+    );
+}
@@ -0,0 +1,57 @@
+//! Tests for rendering ESC/POS output to preview images.
+
+const WIDTH: u32 = 576;
+
+fn golden(name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name);
+    std::fs::read(&path).unwrap_or_else(|e| panic!("Error reading {}: {e}", path.display()))
+}
+
+#[test]
+fn previews_golden_jobs() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.display();
+        let img = adb::preview::render(&std::fs::read(&path).unwrap(), WIDTH).unwrap();
+        assert_eq!(img.width(), WIDTH, "{name}");
+        assert!(img.height() > 0, "{name}");
+        assert!(img.pixels().any(|p| p.0[0] == 0), "{name} is blank");
+    }
+}
+
+#[test]
+fn golden_job_matches_its_text() {
+    // The startup receipt is two centered lines, so every command in it has
+    // to be skipped by its real length for the text to come out right.
+    let job = golden("startup.bin");
+    let text = b"\x1b@\x1ba\x01\x1b-\x01Gram Server Started\n\x1b-\x00\
+        Friday March 14, 2025 at 09:26:53 AM\n\x1ba\x00";
+    let from_job = adb::preview::render(&job, WIDTH).unwrap();
+    let from_text = adb::preview::render(text, WIDTH).unwrap();
+    assert_eq!(
+        from_job.as_raw()[..from_text.as_raw().len()],
+        from_text.as_raw()[..]
+    );
+}
+
+#[test]
+fn skips_commands_by_their_length() {
+    let plain = adb::preview::render(b"\x1b@Hello\n", WIDTH).unwrap();
+    // ESC $ (4 bytes), ESC p (5 bytes), ESC 2 (2 bytes), ESC c 5 (4 bytes)
+    // and GS L (4 bytes) don't draw anything.
+    let with_commands = adb::preview::render(
+        b"\x1b@\x1b$\x00\x00\x1bp\x00\x19\xfa\x1b2\x1bc5\x00\x1dL\x00\x00Hello\n",
+        WIDTH,
+    )
+    .unwrap();
+    assert_eq!(plain, with_commands);
+}
+
+#[test]
+fn rejects_unknown_commands() {
+    assert!(adb::preview::render(b"\x1b@\x1b(A\x04\x00Hello\n", WIDTH).is_err());
+    assert!(adb::preview::render(b"Hello\x1b", WIDTH).is_err());
+}
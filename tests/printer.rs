@@ -0,0 +1,138 @@
+//! Tests for rendering jobs for each printer model, and for the network
+//! printer sink against a fake printer.
+
+use adb::printer::PrintSink;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Accepts connections on a local port, answering status queries as an
+/// online printer would. A connection is dropped once `close` is sent on it.
+struct FakePrinter {
+    port: u16,
+    connections: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    received: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+}
+
+impl FakePrinter {
+    async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let printer = FakePrinter {
+            port: listener.local_addr().unwrap().port(),
+            connections: Default::default(),
+            received: Default::default(),
+        };
+        let connections = printer.connections.clone();
+        let received = printer.received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let received = received.clone();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    let mut data = Vec::<u8>::new();
+                    while let Ok(n) = stream.read(&mut buf).await
+                        && n > 0
+                    {
+                        data.extend(&buf[..n]);
+                        if data.windows(5).any(|w| w == b"close") {
+                            return;
+                        }
+                        let queries = buf[..n].windows(2).filter(|w| w == b"\x10\x04").count();
+                        stream.write_all(&vec![0x12; queries]).await.unwrap();
+                        received.lock().unwrap().extend(&buf[..n]);
+                    }
+                });
+            }
+        });
+        printer
+    }
+
+    fn sink(&self) -> adb::printer::TcpSink {
+        adb::printer::TcpSink::new(&adb::printer::PrinterConfig {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            ..Default::default()
+        })
+    }
+
+    fn connections(&self) -> usize {
+        self.connections.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[tokio::test]
+async fn reuses_one_connection() {
+    let printer = FakePrinter::start().await;
+    let sink = printer.sink();
+
+    let status = sink.status().await.unwrap().unwrap();
+    assert!(status.online);
+    sink.print(b"one").await.unwrap();
+    assert!(sink.status().await.unwrap().is_some());
+    sink.print(b"two").await.unwrap();
+    // Answered after everything sent before it has been read.
+    sink.status().await.unwrap();
+    assert_eq!(printer.connections(), 1);
+
+    let received = printer.received.lock().unwrap().clone();
+    assert!(received.windows(3).any(|w| w == b"one"));
+    assert!(received.windows(3).any(|w| w == b"two"));
+}
+
+#[tokio::test]
+async fn reconnects_after_an_error() {
+    let printer = FakePrinter::start().await;
+    let sink = printer.sink();
+
+    sink.print(b"close").await.unwrap();
+    // The printer hung up, which the status check finds out about.
+    assert!(sink.status().await.is_err());
+    sink.print(b"again").await.unwrap();
+    assert!(sink.status().await.unwrap().is_some());
+    assert_eq!(printer.connections(), 2);
+}
+
+/// `FS ( C`, which switches the character set.
+const CHARACTER_SET: &[u8] = b"\x1c(C";
+
+async fn render(config: adb::printer::PrinterConfig) -> anyhow::Result<Vec<u8>> {
+    let printer = adb::printer::Printer::new(
+        config,
+        std::sync::Arc::new(adb::printer::MemorySink::default()),
+    );
+    let now = chrono::Local::now();
+    printer.render(&adb::gram::startup_receipt(now)).await
+}
+
+#[tokio::test]
+async fn unicode_only_where_supported() {
+    use adb::printer::{Model, PrinterConfig};
+
+    for model in [Model::Generic, Model::T20II] {
+        let job = render(PrinterConfig {
+            model,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(!job.windows(3).any(|w| w == CHARACTER_SET), "{model:?}");
+    }
+    let job = render(PrinterConfig {
+        model: Model::T30II,
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert!(job.windows(3).any(|w| w == CHARACTER_SET));
+
+    // Asking for it on a model without it is still an error.
+    assert!(
+        render(PrinterConfig {
+            model: Model::T20II,
+            unicode: Some(true),
+            ..Default::default()
+        })
+        .await
+        .is_err()
+    );
+}
@@ -0,0 +1,142 @@
+//! Tests for the on-disk print queue and its actor.
+
+mod common;
+
+fn printer(sink: std::sync::Arc<dyn adb::printer::PrintSink>) -> adb::printer::Printer {
+    adb::printer::Printer::new(Default::default(), sink)
+}
+
+async fn wait_for_jobs(sink: &adb::printer::MemorySink, n: usize) -> Vec<Vec<u8>> {
+    for _ in 0..1000 {
+        let jobs = sink.jobs();
+        if jobs.len() >= n {
+            return jobs;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("Only {} of {n} jobs printed", sink.jobs().len());
+}
+
+/// Fails the first `failures` jobs, then prints to memory.
+struct FlakySink {
+    failures: std::sync::atomic::AtomicU32,
+    sink: adb::printer::MemorySink,
+}
+
+impl adb::printer::PrintSink for FlakySink {
+    fn print<'a>(&'a self, job: &'a [u8]) -> adb::printer::BoxFuture<'a, anyhow::Result<()>> {
+        let fail = self
+            .failures
+            .fetch_update(
+                std::sync::atomic::Ordering::SeqCst,
+                std::sync::atomic::Ordering::SeqCst,
+                |n| n.checked_sub(1),
+            )
+            .is_ok();
+        Box::pin(async move {
+            if fail {
+                anyhow::bail!("Connection refused");
+            }
+            self.sink.print(job).await
+        })
+    }
+}
+
+#[tokio::test]
+async fn prints_highest_priority_first() {
+    use adb::queue::Priority;
+
+    let dir = common::temp_path("queue-priority");
+    let sink = std::sync::Arc::new(adb::printer::MemorySink::default());
+    let (queue, actor) = adb::queue::PrintQueue::open("test", &dir, printer(sink.clone())).unwrap();
+    // Everything is queued before the actor starts, so it has to choose.
+    for (data, priority) in [
+        ("low", Priority::Low),
+        ("normal", Priority::Normal),
+        ("high 1", Priority::High),
+        ("high 2", Priority::High),
+    ] {
+        queue.submit(data.into(), priority).await.unwrap();
+    }
+    tokio::spawn(actor.run());
+
+    let jobs = wait_for_jobs(&sink, 4).await;
+    assert_eq!(
+        jobs,
+        [&b"high 1"[..], b"high 2", b"normal", b"low"].map(<[u8]>::to_vec)
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test(start_paused = true)]
+async fn retries_with_backoff() {
+    let dir = common::temp_path("queue-backoff");
+    let sink = std::sync::Arc::new(FlakySink {
+        failures: 2.into(),
+        sink: Default::default(),
+    });
+    let (queue, actor) = adb::queue::PrintQueue::open("test", &dir, printer(sink.clone())).unwrap();
+    tokio::spawn(actor.run());
+
+    let start = tokio::time::Instant::now();
+    let id = queue
+        .submit(b"job".to_vec(), Default::default())
+        .await
+        .unwrap();
+    wait_for_jobs(&sink.sink, 1).await;
+    // Two seconds after the first failure, then four after the second.
+    assert!(start.elapsed() >= std::time::Duration::from_secs(6));
+
+    let status = queue.status(&id).await.unwrap().unwrap();
+    assert_eq!(status.state, adb::queue::JobState::Printed);
+    assert_eq!(status.attempts, 3);
+    assert!(status.last_error.is_none());
+    assert!(!dir.join(format!("{id}.job")).exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn reloads_pending_jobs() {
+    use adb::queue::Priority;
+
+    let dir = common::temp_path("queue-reload");
+    let idle = std::sync::Arc::new(adb::printer::MemorySink::default());
+    let (queue, actor) = adb::queue::PrintQueue::open("test", &dir, printer(idle)).unwrap();
+    queue.submit(b"low".to_vec(), Priority::Low).await.unwrap();
+    queue
+        .submit(b"high".to_vec(), Priority::High)
+        .await
+        .unwrap();
+    // Stop without printing anything, as if the server had crashed.
+    drop((queue, actor));
+
+    // A job that printed, but whose bytes weren't cleaned up.
+    std::fs::write(dir.join("0000000000000001.job"), b"printed").unwrap();
+    std::fs::write(
+        dir.join("0000000000000001.json"),
+        serde_json::to_vec(&adb::queue::JobStatus {
+            id: "0000000000000001".to_string(),
+            printer: "test".to_string(),
+            state: adb::queue::JobState::Printed,
+            priority: Priority::High,
+            created_at: chrono::Utc::now(),
+            attempts: 1,
+            last_error: None,
+            printed_at: Some(chrono::Utc::now()),
+        })
+        .unwrap(),
+    )
+    .unwrap();
+
+    let sink = std::sync::Arc::new(adb::printer::MemorySink::default());
+    let (_queue, actor) =
+        adb::queue::PrintQueue::open("test", &dir, printer(sink.clone())).unwrap();
+    tokio::spawn(actor.run());
+
+    let jobs = wait_for_jobs(&sink, 2).await;
+    assert_eq!(jobs, [b"high".to_vec(), b"low".to_vec()]);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(sink.jobs().len(), 2);
+    assert!(!dir.join("0000000000000001.job").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,64 @@
+//! Tests for parsing `DLE EOT` status responses.
+
+use adb::status::{PrinterStatus, parse};
+
+/// Every response with no flags set.
+const IDLE: u8 = 0x12;
+
+#[test]
+fn ready() {
+    assert_eq!(
+        parse([IDLE; 4]).unwrap(),
+        PrinterStatus {
+            online: true,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn offline_with_cover_open() {
+    let status = parse([IDLE | 0x08, IDLE | 0x04, IDLE, IDLE]).unwrap();
+    assert!(!status.online);
+    assert!(status.cover_open);
+    assert!(!status.paper_end);
+}
+
+#[test]
+fn paper_near_end() {
+    let status = parse([IDLE, IDLE, IDLE, IDLE | 0x0c]).unwrap();
+    assert!(status.paper_near_end);
+    assert!(!status.paper_end);
+    // Only one of the two near-end bits isn't enough.
+    assert!(
+        !parse([IDLE, IDLE, IDLE, IDLE | 0x04])
+            .unwrap()
+            .paper_near_end
+    );
+}
+
+#[test]
+fn paper_end() {
+    // From the roll sensor...
+    let status = parse([IDLE, IDLE, IDLE, IDLE | 0x60]).unwrap();
+    assert!(status.paper_end);
+    assert!(!status.paper_near_end);
+    // ...or as the reason the printer stopped.
+    assert!(parse([IDLE, IDLE | 0x20, IDLE, IDLE]).unwrap().paper_end);
+}
+
+#[test]
+fn errors() {
+    let status = parse([IDLE, IDLE, IDLE | 0x04, IDLE]).unwrap();
+    assert!(status.cutter_error);
+    assert!(!status.unrecoverable_error);
+    let status = parse([IDLE, IDLE, IDLE | 0x08 | 0x20, IDLE]).unwrap();
+    assert!(status.unrecoverable_error);
+    assert!(status.auto_recoverable_error);
+}
+
+#[test]
+fn rejects_bytes_that_are_not_status_responses() {
+    assert!(parse([IDLE, 0x00, IDLE, IDLE]).is_err());
+    assert!(parse([IDLE, IDLE, IDLE, b'A']).is_err());
+}
@@ -0,0 +1,97 @@
+//! Tests for reading the usage ledger and checking budgets.
+
+mod common;
+
+fn entry_line(cost_usd: f64) -> String {
+    entry_line_at(chrono::Utc::now(), adb::usage::CallSite::Gram, cost_usd)
+}
+
+fn entry_line_at(
+    at: chrono::DateTime<chrono::Utc>,
+    call_site: adb::usage::CallSite,
+    cost_usd: f64,
+) -> String {
+    let entry = adb::usage::LedgerEntry {
+        at,
+        call_site,
+        model: "test-model".to_string(),
+        usage: Default::default(),
+        cost_usd,
+    };
+    format!("{}\n", serde_json::to_string(&entry).unwrap())
+}
+
+fn append(path: &std::path::Path, text: &str) {
+    use std::io::Write;
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap()
+        .write_all(text.as_bytes())
+        .unwrap();
+}
+
+#[tokio::test]
+async fn skips_bad_lines() {
+    let path = common::temp_path("usage-bad-lines.jsonl");
+    let ledger = common::test_ledger(&path, Some(1.5));
+    append(&path, &entry_line(1.0));
+    append(&path, "not json\n");
+    append(&path, &entry_line(1.0));
+    append(&path, r#"{"at":"2025-03-"#);
+
+    assert_eq!(ledger.entries().await.unwrap().len(), 2);
+    let exceeded = ledger.check_budget().await.unwrap().unwrap();
+    assert_eq!(exceeded.period, "daily");
+    assert_eq!(exceeded.spent, 2.0);
+}
+
+#[tokio::test]
+async fn budget_includes_later_appends() {
+    let path = common::temp_path("usage-appends.jsonl");
+    let ledger = common::test_ledger(&path, Some(1.0));
+    assert!(ledger.check_budget().await.unwrap().is_none());
+
+    append(&path, &entry_line(0.5));
+    assert!(ledger.check_budget().await.unwrap().is_none());
+
+    // Half of a line that's still being written isn't counted until it's
+    // finished.
+    let line = entry_line(0.6);
+    let (first, rest) = line.split_at(line.len() / 2);
+    append(&path, first);
+    assert!(ledger.check_budget().await.unwrap().is_none());
+    append(&path, rest);
+    let exceeded = ledger.check_budget().await.unwrap().unwrap();
+    assert!((exceeded.spent - 1.1).abs() < 1e-9);
+
+    // Starting a new ledger starts the count over.
+    std::fs::write(&path, entry_line(0.25)).unwrap();
+    assert!(ledger.check_budget().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn report_limits_days_not_rows() {
+    use adb::usage::CallSite;
+    use chrono::TimeZone;
+
+    let path = common::temp_path("usage-report.jsonl");
+    let ledger = common::test_ledger(&path, None);
+    let noon = |day| {
+        chrono::Local
+            .with_ymd_and_hms(2025, 3, day, 12, 0, 0)
+            .unwrap()
+            .to_utc()
+    };
+    append(&path, &entry_line_at(noon(12), CallSite::Gram, 0.1));
+    append(&path, &entry_line_at(noon(13), CallSite::Gram, 0.1));
+    append(&path, &entry_line_at(noon(14), CallSite::Brief, 0.1));
+    append(&path, &entry_line_at(noon(14), CallSite::Gram, 0.1));
+
+    let report = ledger.report(2).await.unwrap();
+    assert!(report.contains("2025-03-14 brief"), "{report}");
+    assert!(report.contains("2025-03-14 gram"), "{report}");
+    assert!(report.contains("2025-03-13 gram"), "{report}");
+    assert!(!report.contains("2025-03-12"), "{report}");
+}